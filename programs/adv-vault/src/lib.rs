@@ -1,32 +1,97 @@
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::keccak;
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
 
 declare_id!("ZB1BxyVhCwFECQoV7bjoun2pMk1yPvz3PGVoKu4d4m5");
 
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+pub const MS_PER_SLOT: i64 = 400;
+
+pub const MAX_STAKE_YEARS: u8 = 2;
+pub const MIN_DORMANCY_PERIOD: i64 = 90 * 24 * 60 * 60;
+pub const REWARD_LEDGER_CAPACITY: usize = 32;
+pub const MERKLE_BITMAP_BYTES: usize = 1024;
+pub const RATE_HISTORY_CAPACITY: usize = 16;
+
+pub const RATE_STREAK_BONUS: u8 = 0;
+pub const RATE_MAX_STREAK_BONUS: u8 = 1;
+pub const RATE_VETERAN_BONUS: u8 = 2;
+pub const RATE_MAX_VETERAN_BONUS: u8 = 3;
+pub const RATE_MAX_LOCK_BONUS: u8 = 4;
+pub const RATE_CONGESTION_BONUS: u8 = 5;
+pub const RATE_EPOCH_BONUS: u8 = 6;
+pub const RATE_CURVE_START_BONUS: u8 = 7;
+pub const RATE_FULL_SUBSCRIPTION_BONUS: u8 = 8;
+pub const RATE_MANAGEMENT_FEE: u8 = 9;
+pub const RATE_KEEPER_TIP: u8 = 10;
+pub const RATE_COMMITMENT_FORFEIT: u8 = 11;
+pub const RATE_RESERVE_FEE_SHARE: u8 = 12;
+pub const RATE_FEE_TO_RESERVE: u8 = 13;
+pub const RATE_LONG_UNBOND_BONUS: u8 = 14;
+pub const RATE_UTILIZATION_BASE_BONUS: u8 = 15;
+pub const RATE_UTILIZATION_KINK_BONUS: u8 = 16;
+pub const RATE_DCA_BONUS: u8 = 17;
+pub const RATE_MAX_DCA_BONUS: u8 = 18;
+
+pub const EVENT_STAKE: u32 = 1 << 0;
+pub const EVENT_WITHDRAW: u32 = 1 << 1;
+pub const EVENT_CLAIM: u32 = 1 << 2;
+pub const EVENT_ADMIN: u32 = 1 << 3;
+pub const EVENT_NOTIFY: u32 = 1 << 4;
+pub const EVENT_ALL: u32 = EVENT_STAKE | EVENT_WITHDRAW | EVENT_CLAIM | EVENT_ADMIN | EVENT_NOTIFY;
+
 #[program]
 pub mod advanced_vault {
     use super::*;
 
     pub fn create_vault(ctx: Context<CreateVault>) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
 
-        vault.initialize(ctx.accounts.admin.key(), ctx.bumps.vault);
+        vault.initialize(
+            ctx.accounts.admin.key(),
+            ctx.bumps.vault,
+            clock.unix_timestamp,
+        );
 
         msg!("Vault created by admin: {}", vault.admin);
         Ok(())
     }
 
-    pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64, stake_years: u8) -> Result<()> {
+    pub fn stake_tokens(
+        ctx: Context<StakeTokens>,
+        amount: u64,
+        stake_years: u8,
+        acknowledge_long_lock: bool,
+    ) -> Result<()> {
         require!(amount > 0, VaultError::InvalidAmount);
         require!(
-            stake_years >= 1 && stake_years <= 2,
+            ctx.accounts.vault.is_valid_stake_period(stake_years),
             VaultError::InvalidStakePeriod
         );
+        require!(
+            stake_years >= ctx.accounts.vault.min_stake_years_for(amount),
+            VaultError::LockTooShortForAmount
+        );
+        require!(
+            acknowledge_long_lock || !ctx.accounts.vault.is_long_lock(stake_years),
+            VaultError::LongLockNotAcknowledged
+        );
+
+        check_same_mint(
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.vault_token_account,
+        )?;
 
         let user_stake = &mut ctx.accounts.user_stake;
         let clock = Clock::get()?;
 
+        ctx.accounts.vault.check_clock(&clock)?;
+        require!(user_stake.is_reusable(), VaultError::StakeAlreadyActive);
+        require!(!user_stake.in_cohort, VaultError::CohortShareUnclaimed);
+
         let cpi_accounts = Transfer {
             from: ctx.accounts.user_token_account.to_account_info(),
             to: ctx.accounts.vault_token_account.to_account_info(),
@@ -36,283 +101,9101 @@ pub mod advanced_vault {
         let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
         token::transfer(cpi_ctx, amount)?;
 
+        let vault = &mut ctx.accounts.vault;
+        vault.accrue_management_fee(clock.unix_timestamp)?;
+        let reward_reserve = vault.reward_reserve(ctx.accounts.vault_token_account.amount);
+        let utilization_bonus_bps = vault.calculate_utilization_bonus_bps();
+        vault.add_staked(amount)?;
+
         user_stake.create_stake(
             ctx.accounts.user.key(),
             amount,
             stake_years,
-            clock.unix_timestamp,
+            &clock,
+            vault.settlement_delay_seconds,
             ctx.bumps.user_stake,
         )?;
 
-        emit!(StakeCreatedEvent {
-            user: ctx.accounts.user.key(),
-            amount,
-            stake_years,
-            unlock_time: user_stake.unlock_time,
-        });
+        user_stake.set_max_lock_bonus(vault.max_lock_bonus_for(stake_years));
+        user_stake.set_streak_bonus(
+            vault.calculate_streak_bonus_bps(ctx.accounts.user_stats.current_streak),
+        );
+
+        let user_profile = &mut ctx.accounts.user_profile;
+        user_profile.record_stake(
+            ctx.accounts.user.key(),
+            ctx.bumps.user_profile,
+            clock.unix_timestamp,
+        );
+        let tenure_at_unlock = user_profile.tenure(user_stake.unlock_time);
+        user_stake.set_veteran_bonus(vault.calculate_veteran_bonus_bps(tenure_at_unlock) as u16);
+        vault.apply_bonding_curve(user_stake)?;
+        if stake_years > 0 {
+            user_stake.set_utilization_bonus(utilization_bonus_bps);
+        }
+
+        let committed_reward = user_stake.calculate_committed_reward(vault)?;
+        vault.check_reserve_buffer(reward_reserve, committed_reward)?;
+        vault.commit_reward(committed_reward)?;
+        user_stake.set_committed_reward(committed_reward);
+
+        if vault.is_cohort_open() {
+            user_stake.join_cohort(clock.unix_timestamp);
+            vault.add_cohort_member(user_stake, clock.unix_timestamp)?;
+        }
+
+        if vault.emits(EVENT_STAKE) {
+            emit!(StakeCreatedEvent {
+                user: ctx.accounts.user.key(),
+                amount,
+                stake_years,
+                unlock_time: user_stake.unlock_time,
+            });
+        }
 
         msg!("User staked {} tokens for {} years", amount, stake_years);
         Ok(())
     }
 
-    pub fn withdraw_stake(ctx: &mut Context<WithdrawStake>) -> Result<()> {
-        let vault = &ctx.accounts.vault;
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>) -> Result<()> {
+        let accounts = ctx.accounts;
+
+        process_withdrawal(Withdrawal {
+            vault: &mut accounts.vault,
+            user_stake: &mut accounts.user_stake,
+            user_stats: &mut accounts.user_stats,
+            user_stats_bump: ctx.bumps.user_stats,
+            user_profile: &mut accounts.user_profile,
+            user_profile_bump: ctx.bumps.user_profile,
+            user: accounts.user.key(),
+            destination: &accounts.user_token_account,
+            vault_token_account: &accounts.vault_token_account,
+            stake_decimals: accounts.mint.decimals,
+            loyalty_mint: &accounts.loyalty_mint,
+            user_loyalty_token_account: &accounts.user_loyalty_token_account,
+            reward_ledger: &mut accounts.reward_ledger,
+            savings_vault: &mut accounts.savings_vault,
+            guardian: accounts.guardian.as_ref().map(|guardian| guardian.key()),
+            keeper_token_account: None,
+            defer_reward: true,
+            token_program: &accounts.token_program,
+        })?;
+        Ok(())
+    }
+
+    pub fn adjust_stake(
+        ctx: Context<AdjustStake>,
+        new_amount: Option<u64>,
+        new_unlock_time: Option<i64>,
+        new_stake_years: Option<u8>,
+    ) -> Result<()> {
         let user_stake = &mut ctx.accounts.user_stake;
+
+        let old_amount = user_stake.amount;
+        let old_unlock_time = user_stake.unlock_time;
+        let old_stake_years = user_stake.stake_years;
+
+        let amount = new_amount.unwrap_or(old_amount);
+        let unlock_time = new_unlock_time.unwrap_or(old_unlock_time);
+        let stake_years = new_stake_years.unwrap_or(old_stake_years);
+
+        user_stake.check_adjustment(
+            &ctx.accounts.vault,
+            amount,
+            unlock_time,
+            stake_years,
+            ctx.accounts.vault_token_account.amount,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
         let clock = Clock::get()?;
+        vault.accrue_management_fee(clock.unix_timestamp)?;
+        vault.remove_staked(old_amount)?;
+        vault.add_staked(amount)?;
 
-        user_stake.check_if_unlocked(clock.unix_timestamp)?;
+        let update_cohort = user_stake.in_cohort && vault.is_cohort_open();
+        if update_cohort {
+            vault.remove_cohort_member(user_stake)?;
+        }
 
-        let total_return = user_stake.calculate_total_return()?;
+        user_stake.adjust(amount, unlock_time, stake_years);
+        user_stake.update_epoch_floor(vault, clock.unix_timestamp);
 
-        let admin_key = vault.admin;
-        let vault_bump = vault.bump;
-        let seeds = &[b"vault", admin_key.as_ref(), &[vault_bump]];
-        let signer_seeds = &[&seeds[..]];
+        if stake_years != MAX_STAKE_YEARS {
+            user_stake.set_max_lock_bonus(0);
+        }
 
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.vault_token_account.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
+        let committed_reward = if user_stake.has_lapsed_commitment(clock.unix_timestamp) {
+            0
+        } else {
+            user_stake.calculate_committed_reward(vault)?
         };
+        vault.release_committed_reward(user_stake.committed_reward);
+        vault.commit_reward(committed_reward)?;
+        user_stake.set_committed_reward(committed_reward);
 
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            signer_seeds,
-        );
-        token::transfer(cpi_ctx, total_return)?;
-
-        user_stake.mark_as_withdrawn();
+        if update_cohort {
+            user_stake.resize_cohort(clock.unix_timestamp);
+            vault.add_cohort_member(user_stake, clock.unix_timestamp)?;
+        }
 
-        emit!(StakeWithdrawnEvent {
-            user: ctx.accounts.user.key(),
-            original_amount: user_stake.amount,
-            total_return,
-            multiplier: user_stake.get_multiplier(),
-        });
+        if vault.emits(EVENT_STAKE) {
+            emit!(StakeAdjustedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                old_amount,
+                new_amount: amount,
+                old_unlock_time,
+                new_unlock_time: unlock_time,
+                old_stake_years,
+                new_stake_years: stake_years,
+            });
+        }
 
         msg!(
-            "User withdrew {} tokens ({}x multiplier)",
-            total_return,
-            user_stake.get_multiplier()
+            "Admin adjusted stake for {}: amount {} -> {}, unlock {} -> {}, years {} -> {}",
+            user_stake.user,
+            old_amount,
+            amount,
+            old_unlock_time,
+            unlock_time,
+            old_stake_years,
+            stake_years
         );
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct CreateVault<'info> {
-    #[account(
-        init,                                       
-        payer = admin,                                
-        space = Vault::INIT_SPACE,                     
-        seeds = [b"vault", admin.key().as_ref()],      
-        bump                                           
-    )]
-    pub vault: Account<'info, Vault>,
+    pub fn set_beneficiary(
+        ctx: Context<GuardedUpdateStake>,
+        beneficiary: Option<Pubkey>,
+        dormancy_period: i64,
+    ) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
 
-    #[account(mut)]
-    pub admin: Signer<'info>,
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        user_stake.check_guardian(
+            ctx.accounts
+                .guardian
+                .as_ref()
+                .map(|guardian| guardian.key()),
+        )?;
 
-    pub system_program: Program<'info, System>,
-}
+        user_stake.set_beneficiary(beneficiary, dormancy_period)?;
 
-#[derive(Accounts)]
-pub struct StakeTokens<'info> {
-    #[account(
-        seeds = [b"vault", vault.admin.as_ref()],     
-        bump = vault.bump                              
-    )]
-    pub vault: Account<'info, Vault>,
+        msg!(
+            "User {} set beneficiary {:?} with dormancy period {}s",
+            user_stake.user,
+            beneficiary,
+            dormancy_period
+        );
+        Ok(())
+    }
 
-    #[account(
-        init,                                         
-        payer = user,                                   
-        space = UserStake::INIT_SPACE,           
-        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],  
-        bump
-    )]
-    pub user_stake: Account<'info, UserStake>,
+    pub fn claim_as_beneficiary(ctx: Context<ClaimAsBeneficiary>) -> Result<()> {
+        let accounts = ctx.accounts;
+        let user = accounts.user_stake.user;
+        let beneficiary = accounts.beneficiary.key();
+        let clock = Clock::get()?;
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+        require!(
+            accounts.user_stake.beneficiary == Some(beneficiary),
+            VaultError::UnauthorizedBeneficiary
+        );
+        accounts.user_stake.check_if_dormant(clock.unix_timestamp)?;
 
-    #[account(
-        mut,                                         
-        associated_token::mint = mint,             
-        associated_token::authority = user        
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
+        let total_return = process_withdrawal(Withdrawal {
+            vault: &mut accounts.vault,
+            user_stake: &mut accounts.user_stake,
+            user_stats: &mut accounts.user_stats,
+            user_stats_bump: ctx.bumps.user_stats,
+            user_profile: &mut accounts.user_profile,
+            user_profile_bump: ctx.bumps.user_profile,
+            user,
+            destination: &accounts.beneficiary_token_account,
+            vault_token_account: &accounts.vault_token_account,
+            stake_decimals: accounts.mint.decimals,
+            loyalty_mint: &accounts.loyalty_mint,
+            user_loyalty_token_account: &accounts.beneficiary_loyalty_token_account,
+            reward_ledger: &mut accounts.reward_ledger,
+            savings_vault: &mut None,
+            guardian: accounts.guardian.as_ref().map(|guardian| guardian.key()),
+            keeper_token_account: None,
+            defer_reward: false,
+            token_program: &accounts.token_program,
+        })?;
 
-    #[account(
-        init_if_needed,                                 
-        payer = user,                                  
-        associated_token::mint = mint,           
-        associated_token::authority = vault      
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+        if accounts.vault.emits(EVENT_CLAIM) {
+            emit!(BeneficiaryClaimedEvent {
+                user,
+                beneficiary,
+                total_return,
+            });
+        }
 
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
+        msg!(
+            "Beneficiary {} claimed {} tokens from dormant stake of {}",
+            beneficiary,
+            total_return,
+            user
+        );
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct WithdrawStake<'info> {
-    #[account(
-        seeds = [b"vault", vault.admin.as_ref()],
-        bump = vault.bump
-    )]
-    pub vault: Account<'info, Vault>,
+    pub fn set_loyalty_mint(ctx: Context<SetLoyaltyMint>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
 
-    #[account(
-        mut,                                         
-        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
-        bump = user_stake.bump,
-        has_one = user @ VaultError::UnauthorizedUser
-    )]
-    pub user_stake: Account<'info, UserStake>,
+        vault.set_loyalty_mint(ctx.accounts.loyalty_mint.key());
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+        msg!(
+            "Vault loyalty mint set to {}",
+            ctx.accounts.loyalty_mint.key()
+        );
+        Ok(())
+    }
 
-    #[account(
-        init_if_needed,                                 
-        payer = user,                                  
-        associated_token::mint = mint,
-        associated_token::authority = user
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub fn set_installment_schedule(
+        ctx: Context<UpdateVault>,
+        installment_count: u8,
+        installment_interval: i64,
+    ) -> Result<()> {
+        if installment_count > 0 {
+            require!(
+                installment_interval > 0,
+                VaultError::InvalidInstallmentSchedule
+            );
+        }
 
-    #[account(
-        mut,                                       
-        associated_token::mint = mint,
-        associated_token::authority = vault
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+        let vault = &mut ctx.accounts.vault;
+        vault.set_installment_schedule(installment_count, installment_interval);
 
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
+        msg!(
+            "Vault installment schedule set to {} installments every {}s",
+            installment_count,
+            installment_interval
+        );
+        Ok(())
+    }
 
-#[account]
-#[derive(InitSpace)]
-pub struct Vault {
-    pub admin: Pubkey,
-    pub bump: u8,
-}
+    pub fn claim_installment(ctx: Context<ClaimReward>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
 
-impl Vault {
-    pub fn initialize(&mut self, admin: Pubkey, bump: u8) {
-        self.admin = admin;
-        self.bump = bump;
-    }
-}
+        vault.check_clock(&clock)?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserStake {
-    pub user: Pubkey,
-    pub amount: u64,
-    pub stake_years: u8,
-    pub stake_time: i64,
-    pub unlock_time: i64,
-    pub is_withdrawn: bool,
-    pub bump: u8,
-}
+        let (installments_due, amount) = user_stake.claimable_installments(clock.unix_timestamp)?;
+        require!(installments_due > 0, VaultError::InstallmentNotDue);
 
-impl UserStake {
-    pub fn create_stake(
-        &mut self,
-        user: Pubkey,
-        amount: u64,
-        stake_years: u8,
-        current_time: i64,
-        bump: u8,
-    ) -> Result<()> {
-        self.user = user;
-        self.amount = amount;
-        self.stake_years = stake_years;
-        self.stake_time = current_time;
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
 
-        let seconds_per_year = 365 * 24 * 60 * 60;
-        let lock_duration = (stake_years as i64) * seconds_per_year;
+        user_stake.record_installments_paid(installments_due);
+        vault.settle_outstanding_payout(amount);
 
-        self.unlock_time = current_time
-            .checked_add(lock_duration)
-            .ok_or::<E>(VaultError::MathOverflow.into())?;
+        record_reward(
+            vault,
+            &mut ctx.accounts.reward_ledger,
+            ctx.accounts.user.key(),
+            amount,
+            clock.unix_timestamp,
+        )?;
 
-        self.is_withdrawn = false;
-        self.bump = bump;
+        if vault.emits(EVENT_CLAIM) {
+            emit!(InstallmentClaimedEvent {
+                user: ctx.accounts.user.key(),
+                installments_paid: user_stake.installments_paid,
+                installment_count: user_stake.installment_count,
+                amount,
+            });
+        }
 
+        msg!(
+            "User claimed {} tokens ({}/{} installments paid)",
+            amount,
+            user_stake.installments_paid,
+            user_stake.installment_count
+        );
         Ok(())
     }
 
-    pub fn check_if_unlocked(&self, current_time: i64) -> Result<()> {
-        require!(!self.is_withdrawn, VaultError::AlreadyWithdrawn);
-        require!(current_time >= self.unlock_time, VaultError::StillLocked);
+    pub fn set_streak_bonus(
+        ctx: Context<UpdateVault>,
+        streak_bonus_bps: u16,
+        max_streak_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            streak_bonus_bps as u64 <= BPS_DENOMINATOR
+                && max_streak_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let old_streak_bonus_bps = vault.streak_bonus_bps;
+        let old_max_streak_bonus_bps = vault.max_streak_bonus_bps;
+        vault.set_streak_bonus(streak_bonus_bps, max_streak_bonus_bps);
+        vault.record_rate_change(
+            RATE_STREAK_BONUS,
+            old_streak_bonus_bps,
+            streak_bonus_bps,
+            timestamp,
+        );
+        vault.record_rate_change(
+            RATE_MAX_STREAK_BONUS,
+            old_max_streak_bonus_bps,
+            max_streak_bonus_bps,
+            timestamp,
+        );
+
+        msg!(
+            "Vault streak bonus set to {} bps per completed stake, capped at {} bps",
+            streak_bonus_bps,
+            max_streak_bonus_bps
+        );
         Ok(())
     }
 
-    pub fn calculate_total_return(&self) -> Result<u64> {
-        let multiplier = self.get_multiplier();
+    pub fn set_long_lock_threshold(
+        ctx: Context<UpdateVault>,
+        long_lock_threshold: u8,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.set_long_lock_threshold(long_lock_threshold);
 
-        self.amount
-            .checked_mul(multiplier as u64)
-            .ok_or(VaultError::MathOverflow.into())
+        msg!(
+            "Vault long lock threshold set to {} years",
+            long_lock_threshold
+        );
+        Ok(())
     }
 
-    pub fn get_multiplier(&self) -> u8 {
-        match self.stake_years {
-            1 => 1,
-            2 => 2,
-            _ => 1,
-        }
-    }
+    pub fn rescue_withdraw(ctx: Context<RescueWithdraw>) -> Result<()> {
+        let accounts = ctx.accounts;
 
-    pub fn mark_as_withdrawn(&mut self) {
-        self.is_withdrawn = true;
+        msg!(
+            "Rescue withdrawal to token account {}",
+            accounts.destination_token_account.key()
+        );
+
+        process_withdrawal(Withdrawal {
+            vault: &mut accounts.vault,
+            user_stake: &mut accounts.user_stake,
+            user_stats: &mut accounts.user_stats,
+            user_stats_bump: ctx.bumps.user_stats,
+            user_profile: &mut accounts.user_profile,
+            user_profile_bump: ctx.bumps.user_profile,
+            user: accounts.user.key(),
+            destination: &accounts.destination_token_account,
+            vault_token_account: &accounts.vault_token_account,
+            stake_decimals: accounts.mint.decimals,
+            loyalty_mint: &accounts.loyalty_mint,
+            user_loyalty_token_account: &accounts.user_loyalty_token_account,
+            reward_ledger: &mut accounts.reward_ledger,
+            savings_vault: &mut accounts.savings_vault,
+            guardian: accounts.guardian.as_ref().map(|guardian| guardian.key()),
+            keeper_token_account: None,
+            defer_reward: true,
+            token_program: &accounts.token_program,
+        })?;
+        Ok(())
     }
-}
 
-#[event]
+    pub fn set_commitment_forfeit(
+        ctx: Context<UpdateVault>,
+        commitment_forfeit_bps: u16,
+    ) -> Result<()> {
+        require!(
+            commitment_forfeit_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_commitment_forfeit_bps = vault.commitment_forfeit_bps;
+        vault.set_commitment_forfeit(commitment_forfeit_bps);
+        vault.record_rate_change(
+            RATE_COMMITMENT_FORFEIT,
+            old_commitment_forfeit_bps,
+            commitment_forfeit_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault commitment forfeit set to {} bps",
+            commitment_forfeit_bps
+        );
+        Ok(())
+    }
+
+    pub fn set_commitment_goal(ctx: Context<UpdateStake>, goal_deadline: i64) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            user_stake.goal_deadline == 0,
+            VaultError::CommitmentGoalAlreadySet
+        );
+        require!(
+            goal_deadline > clock.unix_timestamp,
+            VaultError::InvalidGoalDeadline
+        );
+
+        user_stake.set_commitment_goal(goal_deadline);
+
+        msg!(
+            "User {} committed to a goal due at {}",
+            user_stake.user,
+            goal_deadline
+        );
+        Ok(())
+    }
+
+    pub fn attest_success(ctx: Context<AdminUpdateStake>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(user_stake.goal_deadline != 0, VaultError::NoCommitmentGoal);
+        require!(!user_stake.succeeded, VaultError::CommitmentAlreadyAttested);
+        require!(
+            clock.unix_timestamp <= user_stake.goal_deadline,
+            VaultError::GoalDeadlinePassed
+        );
+
+        user_stake.mark_goal_succeeded();
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(CommitmentAttestedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                goal_deadline: user_stake.goal_deadline,
+            });
+        }
+
+        msg!("Admin attested goal success for {}", user_stake.user);
+        Ok(())
+    }
+
+    pub fn set_management_fee(ctx: Context<UpdateVault>, management_fee_bps: u16) -> Result<()> {
+        require!(
+            management_fee_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let old_management_fee_bps = vault.management_fee_bps;
+        vault.accrue_management_fee(timestamp)?;
+        vault.set_management_fee(management_fee_bps);
+        vault.record_rate_change(
+            RATE_MANAGEMENT_FEE,
+            old_management_fee_bps,
+            management_fee_bps,
+            timestamp,
+        );
+
+        msg!(
+            "Vault management fee set to {} bps per year",
+            management_fee_bps
+        );
+        Ok(())
+    }
+
+    pub fn claim_management_fee(ctx: Context<ClaimManagementFee>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        vault.accrue_management_fee(clock.unix_timestamp)?;
+        require!(
+            vault.accrued_management_fee > 0,
+            VaultError::NoManagementFeeAccrued
+        );
+
+        vault.sync_reserve_stake(ctx.accounts.vault_token_account.amount);
+        let claimable = vault.claimable_management_fee(ctx.accounts.vault_token_account.amount);
+        require!(claimable > 0, VaultError::NoFeeSurplus);
+
+        let reserve_stake_share = vault.calculate_reserve_fee_share(claimable)?;
+        let remaining = claimable
+            .checked_sub(reserve_stake_share)
+            .ok_or(VaultError::MathOverflow)?;
+        let to_reserve = vault.calculate_fee_to_reserve(remaining)?;
+        let amount = remaining
+            .checked_sub(to_reserve)
+            .ok_or(VaultError::MathOverflow)?;
+
+        if amount > 0 {
+            transfer_from_vault(
+                vault,
+                &ctx.accounts.vault_token_account,
+                &ctx.accounts.admin_token_account,
+                &ctx.accounts.token_program,
+                amount,
+            )?;
+        }
+
+        vault.deduct_management_fee(claimable);
+        vault.share_fee_with_reserve_stakers(reserve_stake_share)?;
+
+        if vault.emits(EVENT_CLAIM) {
+            emit!(ManagementFeeClaimedEvent {
+                admin: ctx.accounts.admin.key(),
+                amount,
+                reserve_stake_share,
+                to_reserve,
+            });
+        }
+
+        msg!(
+            "Admin claimed {} tokens of management fees, {} to reserve stakers, {} kept as reward reserve",
+            amount,
+            reserve_stake_share,
+            to_reserve
+        );
+        Ok(())
+    }
+
+    pub fn set_max_reward_per_stake(
+        ctx: Context<UpdateVault>,
+        max_reward_per_stake: u64,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.set_max_reward_per_stake(max_reward_per_stake);
+
+        msg!("Vault max reward per stake set to {}", max_reward_per_stake);
+        Ok(())
+    }
+
+    pub fn set_event_flags(ctx: Context<UpdateVault>, event_flags: u32) -> Result<()> {
+        require!(event_flags & !EVENT_ALL == 0, VaultError::InvalidEventFlags);
+
+        ctx.accounts.vault.set_event_flags(event_flags);
+
+        msg!("Event flags set to {:#06b}", event_flags);
+        Ok(())
+    }
+
+    pub fn close_cohort(ctx: Context<UpdateVault>, reward_pool: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        require!(vault.is_cohort_open(), VaultError::CohortAlreadyClosed);
+
+        vault.close_cohort(reward_pool, clock.unix_timestamp)?;
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(CohortClosedEvent {
+                total_weight: vault.cohort_total_weight,
+                reward_pool,
+                closed_at: clock.unix_timestamp,
+            });
+        }
+
+        msg!(
+            "Cohort closed with {} reward pool over total weight {}",
+            reward_pool,
+            vault.cohort_total_weight
+        );
+        Ok(())
+    }
+
+    pub fn set_cohort_target(
+        ctx: Context<UpdateVault>,
+        cohort_target: u64,
+        cohort_deadline: i64,
+        full_subscription_bonus_bps: u16,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        require!(vault.is_cohort_open(), VaultError::CohortAlreadyClosed);
+        require!(
+            cohort_deadline > clock.unix_timestamp,
+            VaultError::InvalidGoalDeadline
+        );
+        require!(
+            full_subscription_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let old_full_subscription_bonus_bps = vault.full_subscription_bonus_bps;
+        vault.set_cohort_target(
+            cohort_target,
+            cohort_deadline,
+            full_subscription_bonus_bps,
+            clock.unix_timestamp,
+        );
+        vault.record_rate_change(
+            RATE_FULL_SUBSCRIPTION_BONUS,
+            old_full_subscription_bonus_bps,
+            full_subscription_bonus_bps,
+            clock.unix_timestamp,
+        );
+
+        msg!(
+            "Cohort target set to {} by {} for a {} bps bonus",
+            cohort_target,
+            cohort_deadline,
+            full_subscription_bonus_bps
+        );
+        Ok(())
+    }
+
+    pub fn claim_cohort_share(ctx: Context<ClaimReward>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(!vault.is_cohort_open(), VaultError::CohortNotClosed);
+        require!(user_stake.in_cohort, VaultError::NotInCohort);
+
+        let weight = user_stake.cohort_weight(vault.cohort_closed_at);
+        let amount = vault.calculate_cohort_claim(user_stake)?;
+
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        user_stake.leave_cohort();
+        vault.settle_outstanding_payout(amount);
+
+        record_reward(
+            vault,
+            &mut ctx.accounts.reward_ledger,
+            ctx.accounts.user.key(),
+            amount,
+            clock.unix_timestamp,
+        )?;
+
+        if vault.emits(EVENT_CLAIM) {
+            emit!(CohortShareClaimedEvent {
+                user: ctx.accounts.user.key(),
+                weight,
+                amount,
+            });
+        }
+
+        msg!("User claimed {} tokens of the cohort reward pool", amount);
+        Ok(())
+    }
+
+    pub fn set_settlement_delay(
+        ctx: Context<UpdateVault>,
+        settlement_delay_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            settlement_delay_seconds >= 0,
+            VaultError::InvalidSettlementDelay
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_settlement_delay(settlement_delay_seconds);
+
+        msg!(
+            "Vault settlement delay set to {} seconds",
+            settlement_delay_seconds
+        );
+        Ok(())
+    }
+
+    pub fn set_maturity_notification(
+        ctx: Context<UpdateStake>,
+        notify_before_seconds: i64,
+    ) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            notify_before_seconds >= 0,
+            VaultError::InvalidNotificationThreshold
+        );
+
+        user_stake.set_maturity_notification(notify_before_seconds);
+
+        msg!(
+            "User {} will be notified {} seconds before unlock",
+            user_stake.user,
+            notify_before_seconds
+        );
+        Ok(())
+    }
+
+    pub fn emit_maturity_warning(ctx: Context<EmitMaturityWarning>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        user_stake.check_if_in_notification_window(clock.unix_timestamp)?;
+        user_stake.mark_as_warned();
+
+        if vault.emits(EVENT_NOTIFY) {
+            emit!(MaturityApproachingEvent {
+                user: user_stake.user,
+                unlock_time: user_stake.unlock_time,
+                amount: user_stake.amount,
+            });
+        }
+
+        msg!(
+            "Stake of {} unlocks at {}",
+            user_stake.user,
+            user_stake.unlock_time
+        );
+        Ok(())
+    }
+
+    pub fn set_reward_ramp(ctx: Context<UpdateVault>, reward_ramp_seconds: i64) -> Result<()> {
+        // A ramp longer than the shortest lock would cut the reward of every
+        // stake that withdraws on time, not just short-lived ones.
+        require!(
+            (0..=SECONDS_PER_YEAR).contains(&reward_ramp_seconds),
+            VaultError::InvalidRewardRamp
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_reward_ramp(reward_ramp_seconds);
+
+        msg!("Vault reward ramp set to {} seconds", reward_ramp_seconds);
+        Ok(())
+    }
+
+    pub fn create_reward_ledger(ctx: Context<CreateRewardLedger>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let reward_ledger = &mut ctx.accounts.reward_ledger;
+
+        reward_ledger.initialize(vault.key(), ctx.bumps.reward_ledger);
+        vault.set_reward_ledger(reward_ledger.key());
+
+        msg!("Vault reward ledger created at {}", reward_ledger.key());
+        Ok(())
+    }
+
+    pub fn set_loyalty_epoch(
+        ctx: Context<UpdateVault>,
+        epoch_start: i64,
+        epoch_end: i64,
+        epoch_min_stake: u64,
+        epoch_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(epoch_end > epoch_start, VaultError::InvalidEpoch);
+        require!(
+            epoch_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_epoch_bonus_bps = vault.epoch_bonus_bps;
+        vault.set_loyalty_epoch(epoch_start, epoch_end, epoch_min_stake, epoch_bonus_bps);
+        vault.record_rate_change(
+            RATE_EPOCH_BONUS,
+            old_epoch_bonus_bps,
+            epoch_bonus_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Loyalty epoch set from {} to {}: {} bps bonus for holding at least {}",
+            epoch_start,
+            epoch_end,
+            epoch_bonus_bps,
+            epoch_min_stake
+        );
+        Ok(())
+    }
+
+    pub fn compound_across(ctx: Context<CompoundAcross>) -> Result<()> {
+        let source_vault = &mut ctx.accounts.source_vault;
+        let source_stake = &mut ctx.accounts.source_stake;
+        let target_vault = &mut ctx.accounts.target_vault;
+        let target_stake = &mut ctx.accounts.target_stake;
+        let clock = Clock::get()?;
+
+        check_withdrawal_gates(
+            source_vault,
+            source_stake,
+            ctx.accounts
+                .guardian
+                .as_ref()
+                .map(|guardian| guardian.key()),
+            &clock,
+        )?;
+        target_vault.check_clock(&clock)?;
+        source_stake.check_reward_unrestricted(source_vault)?;
+        require!(
+            !source_stake.reward_compounded && !source_stake.has_failed_commitment(),
+            VaultError::NoRewardToCompound
+        );
+        require!(!target_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            clock.unix_timestamp < target_stake.unlock_time,
+            VaultError::TargetStakeUnlocked
+        );
+
+        let bonus_bps =
+            source_stake.calculate_withdrawal_bonus_bps(source_vault, clock.unix_timestamp);
+        let reward = source_stake
+            .calculate_total_return(bonus_bps, source_vault, clock.unix_timestamp)?
+            .saturating_sub(source_stake.amount);
+        require!(reward > 0, VaultError::NoRewardToCompound);
+        let compounded = source_stake.calculate_compounded(reward)?;
+        let paid_out = reward - compounded;
+
+        if bonus_bps > 0 && source_vault.emits(EVENT_WITHDRAW) {
+            emit_rounding(
+                source_stake,
+                source_vault,
+                ctx.accounts.user.key(),
+                bonus_bps,
+                reward,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        if compounded > 0 {
+            transfer_from_vault(
+                source_vault,
+                &ctx.accounts.source_vault_token_account,
+                &ctx.accounts.target_vault_token_account,
+                &ctx.accounts.token_program,
+                compounded,
+            )?;
+        }
+        if paid_out > 0 {
+            transfer_from_vault(
+                source_vault,
+                &ctx.accounts.source_vault_token_account,
+                &ctx.accounts.user_token_account,
+                &ctx.accounts.token_program,
+                paid_out,
+            )?;
+        }
+
+        source_stake.mark_reward_compounded();
+        source_vault.release_committed_reward(source_stake.committed_reward);
+        source_stake.set_committed_reward(0);
+
+        record_reward(
+            source_vault,
+            &mut ctx.accounts.reward_ledger,
+            ctx.accounts.user.key(),
+            reward,
+            clock.unix_timestamp,
+        )?;
+
+        target_vault.accrue_management_fee(clock.unix_timestamp)?;
+        let reward_reserve =
+            target_vault.reward_reserve(ctx.accounts.target_vault_token_account.amount);
+        target_vault.add_staked(compounded)?;
+
+        let update_cohort = target_stake.in_cohort && target_vault.is_cohort_open();
+        if update_cohort {
+            target_vault.remove_cohort_member(target_stake)?;
+        }
+
+        target_stake.compound(compounded)?;
+
+        let committed_reward = target_stake.calculate_committed_reward(target_vault)?;
+        target_vault.release_committed_reward(target_stake.committed_reward);
+        target_vault.check_reserve_buffer(reward_reserve, committed_reward)?;
+        target_vault.commit_reward(committed_reward)?;
+        target_stake.set_committed_reward(committed_reward);
+
+        if update_cohort {
+            target_stake.resize_cohort(clock.unix_timestamp);
+            target_vault.add_cohort_member(target_stake, clock.unix_timestamp)?;
+        }
+
+        if target_vault.emits(EVENT_STAKE) {
+            emit!(RewardCompoundedEvent {
+                user: ctx.accounts.user.key(),
+                source_stake: source_stake.key(),
+                target_stake: target_stake.key(),
+                amount: compounded,
+                paid_out,
+            });
+        }
+
+        msg!(
+            "User compounded {} reward tokens into stake {} and took {} as payout",
+            compounded,
+            target_stake.key(),
+            paid_out
+        );
+        Ok(())
+    }
+
+    pub fn set_max_lock_bonus(ctx: Context<UpdateVault>, max_lock_bonus_bps: u16) -> Result<()> {
+        require!(
+            max_lock_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_max_lock_bonus_bps = vault.max_lock_bonus_bps;
+        vault.set_max_lock_bonus(max_lock_bonus_bps);
+        vault.record_rate_change(
+            RATE_MAX_LOCK_BONUS,
+            old_max_lock_bonus_bps,
+            max_lock_bonus_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault max-lock bonus set to {} bps for {}-year stakes",
+            max_lock_bonus_bps,
+            MAX_STAKE_YEARS
+        );
+        Ok(())
+    }
+
+    pub fn set_allow_flexible(ctx: Context<UpdateVault>, allow_flexible: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.set_allow_flexible(allow_flexible);
+
+        msg!("Vault flexible tier enabled: {}", allow_flexible);
+        Ok(())
+    }
+
+    pub fn verify_stake_collateral(
+        ctx: Context<VerifyStakeCollateral>,
+        user: Pubkey,
+        min_amount: u64,
+        min_unlock: i64,
+    ) -> Result<()> {
+        let user_stake = &ctx.accounts.user_stake;
+
+        user_stake.check_collateral(user, min_amount, min_unlock)?;
+
+        msg!(
+            "Stake of {} holds {} tokens locked until {}",
+            user_stake.user,
+            user_stake.amount,
+            user_stake.unlock_time
+        );
+        Ok(())
+    }
+
+    pub fn set_reward_decay(
+        ctx: Context<UpdateVault>,
+        claim_window_seconds: i64,
+        reward_decay_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            claim_window_seconds >= 0 && reward_decay_seconds >= 0,
+            VaultError::InvalidRewardDecay
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_reward_decay(claim_window_seconds, reward_decay_seconds);
+
+        msg!(
+            "Unclaimed rewards decay over {} seconds starting {} seconds after unlock",
+            reward_decay_seconds,
+            claim_window_seconds
+        );
+        Ok(())
+    }
+
+    pub fn set_veteran_bonus(
+        ctx: Context<UpdateVault>,
+        veteran_bonus_bps: u16,
+        max_veteran_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            veteran_bonus_bps as u64 <= BPS_DENOMINATOR
+                && max_veteran_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let old_veteran_bonus_bps = vault.veteran_bonus_bps;
+        let old_max_veteran_bonus_bps = vault.max_veteran_bonus_bps;
+        vault.set_veteran_bonus(veteran_bonus_bps, max_veteran_bonus_bps);
+        vault.record_rate_change(
+            RATE_VETERAN_BONUS,
+            old_veteran_bonus_bps,
+            veteran_bonus_bps,
+            timestamp,
+        );
+        vault.record_rate_change(
+            RATE_MAX_VETERAN_BONUS,
+            old_max_veteran_bonus_bps,
+            max_veteran_bonus_bps,
+            timestamp,
+        );
+
+        msg!(
+            "Vault veteran bonus set to {} bps per year of protocol tenure, capped at {} bps",
+            veteran_bonus_bps,
+            max_veteran_bonus_bps
+        );
+        Ok(())
+    }
+
+    pub fn distribute_rewards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DistributeRewards<'info>>,
+        total_amount: u64,
+        weights_bps: Vec<u16>,
+    ) -> Result<()> {
+        require!(total_amount > 0, VaultError::InvalidAmount);
+        require!(
+            !weights_bps.is_empty() && ctx.remaining_accounts.len() == weights_bps.len() * 2,
+            VaultError::InvalidAllocationWeights
+        );
+
+        let amounts = split_by_weights(total_amount, &weights_bps)?;
+
+        for (i, amount) in amounts.into_iter().enumerate() {
+            let vault = Account::<Vault>::try_from(&ctx.remaining_accounts[i * 2])?;
+            let vault_token_account =
+                Account::<TokenAccount>::try_from(&ctx.remaining_accounts[i * 2 + 1])?;
+
+            require_keys_eq!(
+                vault_token_account.key(),
+                get_associated_token_address(&vault.key(), &ctx.accounts.mint.key()),
+                VaultError::InvalidDistributionTarget
+            );
+            require_keys_eq!(
+                vault_token_account.mint,
+                ctx.accounts.mint.key(),
+                VaultError::MintMismatch
+            );
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.funder_token_account.to_account_info(),
+                to: vault_token_account.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            };
+
+            let cpi_ctx =
+                CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+            token::transfer(cpi_ctx, amount)?;
+
+            if vault.emits(EVENT_ADMIN) {
+                emit!(RewardsFundedEvent {
+                    funder: ctx.accounts.funder.key(),
+                    vault: vault.key(),
+                    amount,
+                });
+            }
+        }
+
+        msg!(
+            "Distributed {} reward tokens across {} vaults",
+            total_amount,
+            weights_bps.len()
+        );
+        Ok(())
+    }
+
+    pub fn activate_congestion_bonus(
+        ctx: Context<UpdateVault>,
+        duration: i64,
+        congestion_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(duration > 0, VaultError::InvalidEpoch);
+        require!(
+            congestion_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+        let old_congestion_bonus_bps = vault.congestion_bonus_bps;
+        vault.activate_congestion_bonus(clock.unix_timestamp, duration, congestion_bonus_bps)?;
+        vault.record_rate_change(
+            RATE_CONGESTION_BONUS,
+            old_congestion_bonus_bps,
+            congestion_bonus_bps,
+            clock.unix_timestamp,
+        );
+
+        msg!(
+            "Congestion bonus of {} bps active until {}",
+            congestion_bonus_bps,
+            vault.congestion_end
+        );
+        Ok(())
+    }
+
+    pub fn reserve_report(ctx: Context<ReserveReport>) -> Result<ReserveSnapshot> {
+        let clock = Clock::get()?;
+
+        ctx.accounts
+            .vault
+            .reserve_snapshot(ctx.accounts.vault_token_account.amount, &clock)
+    }
+
+    pub fn set_savings_option(
+        ctx: Context<SetSavingsOption>,
+        savings_lock_seconds: i64,
+    ) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(savings_lock_seconds >= 0, VaultError::InvalidSavingsLock);
+
+        user_stake.set_savings_lock(savings_lock_seconds);
+        ctx.accounts.savings_vault.initialize_if_needed(
+            ctx.accounts.vault.key(),
+            ctx.accounts.user.key(),
+            ctx.bumps.savings_vault,
+        );
+
+        msg!(
+            "User {} routes rewards to savings locked for {} seconds",
+            user_stake.user,
+            savings_lock_seconds
+        );
+        Ok(())
+    }
+
+    pub fn release_savings(ctx: Context<ReleaseSavings>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let savings_vault = &mut ctx.accounts.savings_vault;
+        let clock = Clock::get()?;
+
+        vault.check_clock(&clock)?;
+
+        let amount = savings_vault.release(clock.unix_timestamp)?;
+        vault.settle_outstanding_payout(amount);
+
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        record_reward(
+            vault,
+            &mut ctx.accounts.reward_ledger,
+            ctx.accounts.user.key(),
+            amount,
+            clock.unix_timestamp,
+        )?;
+
+        if vault.emits(EVENT_CLAIM) {
+            emit!(SavingsReleasedEvent {
+                user: ctx.accounts.user.key(),
+                amount,
+            });
+        }
+
+        msg!("User released {} tokens from savings", amount);
+        Ok(())
+    }
+
+    pub fn set_guardian(ctx: Context<GuardedUpdateStake>, guardian: Pubkey) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        user_stake.check_guardian(
+            ctx.accounts
+                .guardian
+                .as_ref()
+                .map(|guardian| guardian.key()),
+        )?;
+
+        user_stake.set_guardian(Some(guardian));
+
+        msg!("User {} set guardian {}", user_stake.user, guardian);
+        Ok(())
+    }
+
+    pub fn remove_guardian(ctx: Context<GuardedUpdateStake>) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        user_stake.check_guardian(
+            ctx.accounts
+                .guardian
+                .as_ref()
+                .map(|guardian| guardian.key()),
+        )?;
+
+        user_stake.set_guardian(None);
+
+        msg!("User {} removed their guardian", user_stake.user);
+        Ok(())
+    }
+
+    pub fn start_merkle_campaign(
+        ctx: Context<StartMerkleCampaign>,
+        merkle_root: [u8; 32],
+        total_allocation: u64,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let vault_key = vault.key();
+        let campaign = &mut ctx.accounts.merkle_campaign;
+
+        vault.settle_outstanding_payout(campaign.remaining_allocation);
+        vault.fund_outstanding_payout(ctx.accounts.vault_token_account.amount, total_allocation)?;
+
+        campaign.start(
+            vault_key,
+            merkle_root,
+            total_allocation,
+            ctx.bumps.merkle_campaign,
+        );
+
+        msg!(
+            "Merkle bonus campaign started with {} tokens allocated",
+            total_allocation
+        );
+        Ok(())
+    }
+
+    pub fn claim_merkle_bonus(
+        ctx: Context<ClaimMerkleBonus>,
+        index: u32,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let campaign = &mut ctx.accounts.merkle_campaign;
+        let user = ctx.accounts.user.key();
+        let clock = Clock::get()?;
+
+        let leaf = keccak::hashv(&[&index.to_le_bytes(), user.as_ref(), &amount.to_le_bytes()]).0;
+        require!(
+            campaign.verify_proof(&proof, leaf),
+            VaultError::InvalidMerkleProof
+        );
+
+        campaign.claim(index, amount)?;
+        vault.settle_outstanding_payout(amount);
+
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        record_reward(
+            vault,
+            &mut ctx.accounts.reward_ledger,
+            user,
+            amount,
+            clock.unix_timestamp,
+        )?;
+
+        if vault.emits(EVENT_CLAIM) {
+            emit!(MerkleBonusClaimedEvent {
+                user,
+                index,
+                amount,
+            });
+        }
+
+        msg!("User claimed {} tokens of merkle bonus", amount);
+        Ok(())
+    }
+
+    pub fn set_dispute_window(
+        ctx: Context<UpdateVault>,
+        dispute_window_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            dispute_window_seconds >= 0,
+            VaultError::InvalidDisputeWindow
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_dispute_window(dispute_window_seconds);
+
+        msg!(
+            "Vault withdrawals held for {} seconds before payout",
+            dispute_window_seconds
+        );
+        Ok(())
+    }
+
+    pub fn dispute_withdrawal(ctx: Context<AdminUpdateStake>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(user_stake.pending_payout > 0, VaultError::NoPendingPayout);
+        require!(
+            clock.unix_timestamp < user_stake.payout_available_at,
+            VaultError::DisputeWindowClosed
+        );
+
+        user_stake.set_disputed(true);
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(PayoutDisputedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                amount: user_stake.pending_payout,
+            });
+        }
+
+        msg!(
+            "Admin disputed pending payout of {} for {}",
+            user_stake.pending_payout,
+            user_stake.user
+        );
+        Ok(())
+    }
+
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, release: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(user_stake.disputed, VaultError::PayoutNotDisputed);
+
+        let amount = user_stake.pending_payout;
+        let (refunded_principal, forfeited_reward) = if release {
+            user_stake.set_disputed(false);
+            (0, 0)
+        } else {
+            let (principal, reward) = user_stake.reject_payout()?;
+            vault.settle_outstanding_payout(amount);
+            let user_stats = &mut ctx.accounts.user_stats;
+            let user_stats_bump = user_stats.bump;
+            user_stats.break_streak(user_stake.user, user_stats_bump);
+
+            transfer_from_vault(
+                vault,
+                &ctx.accounts.vault_token_account,
+                &ctx.accounts.user_token_account,
+                &ctx.accounts.token_program,
+                principal,
+            )?;
+            (principal, reward)
+        };
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(DisputeResolvedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                released: release,
+                refunded_principal,
+                forfeited_reward,
+            });
+        }
+
+        msg!(
+            "Admin resolved dispute for {}: payout released {}",
+            user_stake.user,
+            release
+        );
+        Ok(())
+    }
+
+    pub fn finalize_withdrawal(ctx: Context<FinalizeWithdrawal>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        vault.check_clock(&clock)?;
+
+        let (amount, reward) = user_stake.release_payout(clock.unix_timestamp)?;
+        vault.settle_outstanding_payout(amount);
+
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.destination_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        record_reward(
+            vault,
+            &mut ctx.accounts.reward_ledger,
+            user_stake.user,
+            reward,
+            clock.unix_timestamp,
+        )?;
+
+        let loyalty_amount = user_stake.take_pending_loyalty();
+        if loyalty_amount > 0 {
+            let (Some(loyalty_mint), Some(loyalty_token_account)) = (
+                &ctx.accounts.loyalty_mint,
+                &ctx.accounts.loyalty_token_account,
+            ) else {
+                return err!(VaultError::LoyaltyAccountsRequired);
+            };
+            require!(
+                vault.loyalty_mint == Some(loyalty_mint.key()),
+                VaultError::InvalidLoyaltyMint
+            );
+
+            mint_from_vault(
+                vault,
+                loyalty_mint,
+                loyalty_token_account,
+                &ctx.accounts.token_program,
+                loyalty_amount,
+            )?;
+
+            if vault.emits(EVENT_WITHDRAW) {
+                emit!(LoyaltyMintedEvent {
+                    user: user_stake.user,
+                    amount: loyalty_amount,
+                });
+            }
+        }
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(WithdrawalFinalizedEvent {
+                user: user_stake.user,
+                amount,
+            });
+        }
+
+        msg!(
+            "Finalized withdrawal of {} tokens for {}",
+            amount,
+            user_stake.user
+        );
+        Ok(())
+    }
+
+    pub fn set_min_lock_for_amount(
+        ctx: Context<UpdateVault>,
+        one_year_min_amount: u64,
+        two_year_min_amount: u64,
+    ) -> Result<()> {
+        require!(
+            one_year_min_amount == 0
+                || two_year_min_amount == 0
+                || one_year_min_amount <= two_year_min_amount,
+            VaultError::InvalidMinLockCurve
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_min_lock_for_amount(one_year_min_amount, two_year_min_amount);
+
+        msg!(
+            "Stakes of at least {} need 1 year, at least {} need 2 years",
+            one_year_min_amount,
+            two_year_min_amount
+        );
+        Ok(())
+    }
+
+    pub fn set_auto_withdraw(ctx: Context<UpdateStake>, auto_withdraw: bool) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+
+        user_stake.set_auto_withdraw(auto_withdraw);
+
+        msg!(
+            "User {} auto-withdrawal enabled: {}",
+            user_stake.user,
+            auto_withdraw
+        );
+        Ok(())
+    }
+
+    pub fn set_keeper_tip(ctx: Context<UpdateVault>, keeper_tip_bps: u16) -> Result<()> {
+        require!(
+            keeper_tip_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_keeper_tip_bps = vault.keeper_tip_bps;
+        vault.set_keeper_tip(keeper_tip_bps);
+        vault.record_rate_change(
+            RATE_KEEPER_TIP,
+            old_keeper_tip_bps,
+            keeper_tip_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault keeper tip set to {} bps of the reward",
+            keeper_tip_bps
+        );
+        Ok(())
+    }
+
+    pub fn keeper_withdraw(ctx: Context<KeeperWithdraw>) -> Result<()> {
+        let accounts = ctx.accounts;
+
+        accounts.user_stake.check_auto_withdraw()?;
+
+        process_withdrawal(Withdrawal {
+            vault: &mut accounts.vault,
+            user_stake: &mut accounts.user_stake,
+            user_stats: &mut accounts.user_stats,
+            user_stats_bump: ctx.bumps.user_stats,
+            user_profile: &mut accounts.user_profile,
+            user_profile_bump: ctx.bumps.user_profile,
+            user: accounts.user.key(),
+            destination: &accounts.user_token_account,
+            vault_token_account: &accounts.vault_token_account,
+            stake_decimals: accounts.mint.decimals,
+            loyalty_mint: &accounts.loyalty_mint,
+            user_loyalty_token_account: &accounts.user_loyalty_token_account,
+            reward_ledger: &mut accounts.reward_ledger,
+            savings_vault: &mut accounts.savings_vault,
+            guardian: None,
+            keeper_token_account: Some(&accounts.keeper_token_account),
+            defer_reward: true,
+            token_program: &accounts.token_program,
+        })?;
+        Ok(())
+    }
+
+    pub fn set_clock_tolerance(
+        ctx: Context<UpdateVault>,
+        clock_tolerance_seconds: i64,
+        clock_max_drift_bps: u16,
+    ) -> Result<()> {
+        require!(
+            clock_tolerance_seconds >= 0,
+            VaultError::InvalidClockTolerance
+        );
+        require!(
+            clock_max_drift_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+        vault.set_clock_tolerance(clock_tolerance_seconds, clock_max_drift_bps, &clock);
+
+        msg!(
+            "Vault clock tolerance set to {} seconds plus {} bps drift from slot {}",
+            clock_tolerance_seconds,
+            clock_max_drift_bps,
+            clock.slot
+        );
+        Ok(())
+    }
+
+    pub fn set_bonding_curve(
+        ctx: Context<UpdateVault>,
+        curve_start_bonus_bps: u16,
+        curve_span: u64,
+    ) -> Result<()> {
+        require!(
+            curve_start_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+        require!(
+            curve_start_bonus_bps == 0 || curve_span > 0,
+            VaultError::InvalidBondingCurve
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_curve_start_bonus_bps = vault.curve_start_bonus_bps;
+        vault.set_bonding_curve(curve_start_bonus_bps, curve_span);
+        vault.record_rate_change(
+            RATE_CURVE_START_BONUS,
+            old_curve_start_bonus_bps,
+            curve_start_bonus_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault bonding curve set to {} bps falling to 0 over {} tokens",
+            curve_start_bonus_bps,
+            curve_span
+        );
+        Ok(())
+    }
+
+    pub fn set_reserve_buffer(ctx: Context<UpdateVault>, reserve_buffer_bps: u16) -> Result<()> {
+        require!(
+            reserve_buffer_bps == 0 || reserve_buffer_bps as u64 >= BPS_DENOMINATOR,
+            VaultError::InvalidReserveBuffer
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        vault.set_reserve_buffer(reserve_buffer_bps);
+
+        msg!(
+            "Vault reserve buffer set to {} bps of committed rewards",
+            reserve_buffer_bps
+        );
+        Ok(())
+    }
+
+    pub fn release_forfeited_reward(ctx: Context<AdminUpdateStake>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(user_stake.goal_deadline != 0, VaultError::NoCommitmentGoal);
+        require!(
+            user_stake.has_lapsed_commitment(clock.unix_timestamp),
+            VaultError::CommitmentPending
+        );
+        require!(
+            user_stake.committed_reward > 0,
+            VaultError::NoCommittedReward
+        );
+
+        let released = user_stake.committed_reward;
+        vault.release_committed_reward(released);
+        user_stake.set_committed_reward(0);
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(ForfeitedRewardReleasedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                released,
+            });
+        }
+
+        msg!(
+            "Released {} committed reward from failed commitment of {}",
+            released,
+            user_stake.user
+        );
+        Ok(())
+    }
+
+    pub fn set_reserve_fee_share(
+        ctx: Context<UpdateVault>,
+        reserve_fee_share_bps: u16,
+    ) -> Result<()> {
+        require!(
+            reserve_fee_share_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_reserve_fee_share_bps = vault.reserve_fee_share_bps;
+        vault.set_reserve_fee_share(reserve_fee_share_bps);
+        vault.record_rate_change(
+            RATE_RESERVE_FEE_SHARE,
+            old_reserve_fee_share_bps,
+            reserve_fee_share_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault reserve stakers receive {} bps of management fees",
+            reserve_fee_share_bps
+        );
+        Ok(())
+    }
+
+    pub fn stake_reserve(ctx: Context<StakeReserve>, amount: u64) -> Result<()> {
+        require!(amount > 0, VaultError::InvalidAmount);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.sync_reserve_stake(ctx.accounts.vault_token_account.amount);
+        let shares = vault.add_reserve_stake(amount)?;
+
+        let reserve_stake = &mut ctx.accounts.reserve_stake;
+        reserve_stake.initialize_if_needed(
+            vault.key(),
+            ctx.accounts.user.key(),
+            ctx.bumps.reserve_stake,
+        );
+        reserve_stake.deposit(shares, vault.reserve_epoch)?;
+
+        if vault.emits(EVENT_STAKE) {
+            emit!(ReserveStakedEvent {
+                user: ctx.accounts.user.key(),
+                amount,
+                shares,
+            });
+        }
+
+        msg!(
+            "User staked {} tokens into the reward reserve for {} shares",
+            amount,
+            shares
+        );
+        Ok(())
+    }
+
+    pub fn unstake_reserve(ctx: Context<UnstakeReserve>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let reserve_stake = &mut ctx.accounts.reserve_stake;
+        let vault_balance = ctx.accounts.vault_token_account.amount;
+
+        vault.sync_reserve_stake(vault_balance);
+        let shares = reserve_stake.active_shares(vault.reserve_epoch);
+        let amount = vault.reserve_stake_value(shares)?;
+        require!(amount > 0, VaultError::NoReserveStake);
+        require!(
+            vault.reward_reserve(vault_balance).saturating_sub(amount) >= vault.required_reserve(),
+            VaultError::InsufficientRewardReserve
+        );
+
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        vault.remove_reserve_stake(shares, amount)?;
+        reserve_stake.withdraw();
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(ReserveUnstakedEvent {
+                user: ctx.accounts.user.key(),
+                amount,
+                shares,
+            });
+        }
+
+        msg!("User unstaked {} tokens from the reward reserve", amount);
+        Ok(())
+    }
+
+    pub fn set_fee_to_reserve(ctx: Context<UpdateVault>, fee_to_reserve_bps: u16) -> Result<()> {
+        require!(
+            fee_to_reserve_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_fee_to_reserve_bps = vault.fee_to_reserve_bps;
+        vault.set_fee_to_reserve(fee_to_reserve_bps);
+        vault.record_rate_change(
+            RATE_FEE_TO_RESERVE,
+            old_fee_to_reserve_bps,
+            fee_to_reserve_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault keeps {} bps of claimed management fees as reward reserve",
+            fee_to_reserve_bps
+        );
+        Ok(())
+    }
+
+    pub fn appeal_forfeit(ctx: Context<UpdateStake>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(user_stake.goal_deadline != 0, VaultError::NoCommitmentGoal);
+        require!(
+            user_stake.has_lapsed_commitment(clock.unix_timestamp),
+            VaultError::CommitmentPending
+        );
+        require!(
+            !user_stake.forfeit_appealed && !user_stake.appeal_resolved,
+            VaultError::AppealAlreadyFiled
+        );
+
+        user_stake.appeal_forfeit();
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(ForfeitAppealedEvent {
+                user: user_stake.user,
+                goal_deadline: user_stake.goal_deadline,
+            });
+        }
+
+        msg!("User {} appealed a failed commitment", user_stake.user);
+        Ok(())
+    }
+
+    pub fn resolve_appeal(ctx: Context<AdminUpdateStake>, upheld: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(user_stake.forfeit_appealed, VaultError::NoPendingAppeal);
+
+        if upheld {
+            user_stake.mark_goal_succeeded();
+
+            let committed_reward = user_stake.calculate_committed_reward(vault)?;
+            vault.release_committed_reward(user_stake.committed_reward);
+            vault.commit_reward(committed_reward)?;
+            user_stake.set_committed_reward(committed_reward);
+        }
+        user_stake.resolve_appeal();
+
+        if vault.emits(EVENT_ADMIN) {
+            emit!(AppealResolvedEvent {
+                user: user_stake.user,
+                admin: ctx.accounts.admin.key(),
+                upheld,
+            });
+        }
+
+        msg!(
+            "Admin {} the forfeit appeal of {}",
+            if upheld { "upheld" } else { "rejected" },
+            user_stake.user
+        );
+        Ok(())
+    }
+
+    pub fn set_unbond_bonus(
+        ctx: Context<UpdateVault>,
+        max_unbond_period: i64,
+        long_unbond_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(max_unbond_period >= 0, VaultError::InvalidUnbondPeriod);
+        require!(
+            long_unbond_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let old_long_unbond_bonus_bps = vault.long_unbond_bonus_bps;
+        vault.set_unbond_bonus(max_unbond_period, long_unbond_bonus_bps);
+        vault.record_rate_change(
+            RATE_LONG_UNBOND_BONUS,
+            old_long_unbond_bonus_bps,
+            long_unbond_bonus_bps,
+            Clock::get()?.unix_timestamp,
+        );
+
+        msg!(
+            "Vault unbonding bonus set to {} bps for a {} second unbonding period",
+            long_unbond_bonus_bps,
+            max_unbond_period
+        );
+        Ok(())
+    }
+
+    pub fn set_unbond_period(ctx: Context<SetUnbondPeriod>, unbond_period: i64) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            unbond_period >= user_stake.unbond_period
+                && unbond_period <= ctx.accounts.vault.max_unbond_period,
+            VaultError::InvalidUnbondPeriod
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let reward_reserve = vault.reward_reserve(ctx.accounts.vault_token_account.amount);
+        user_stake.set_unbond_period(
+            unbond_period,
+            vault.calculate_unbond_bonus_bps(unbond_period),
+        );
+
+        let committed_reward = user_stake.calculate_committed_reward(vault)?;
+        vault.release_committed_reward(user_stake.committed_reward);
+        vault.check_reserve_buffer(reward_reserve, committed_reward)?;
+        vault.commit_reward(committed_reward)?;
+        user_stake.set_committed_reward(committed_reward);
+
+        msg!(
+            "User {} chose a {} second unbonding period for a {} bps bonus",
+            user_stake.user,
+            unbond_period,
+            user_stake.unbond_bonus_bps
+        );
+        Ok(())
+    }
+
+    pub fn set_utilization_curve(
+        ctx: Context<UpdateVault>,
+        utilization_capacity: u64,
+        utilization_kink_bps: u16,
+        utilization_base_bonus_bps: u16,
+        utilization_kink_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            utilization_base_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+        require!(
+            utilization_base_bonus_bps == 0
+                || (utilization_capacity > 0
+                    && utilization_kink_bps > 0
+                    && (utilization_kink_bps as u64) < BPS_DENOMINATOR
+                    && utilization_kink_bonus_bps <= utilization_base_bonus_bps),
+            VaultError::InvalidUtilizationCurve
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let old_utilization_base_bonus_bps = vault.utilization_base_bonus_bps;
+        let old_utilization_kink_bonus_bps = vault.utilization_kink_bonus_bps;
+        vault.set_utilization_curve(
+            utilization_capacity,
+            utilization_kink_bps,
+            utilization_base_bonus_bps,
+            utilization_kink_bonus_bps,
+        );
+        vault.record_rate_change(
+            RATE_UTILIZATION_BASE_BONUS,
+            old_utilization_base_bonus_bps,
+            utilization_base_bonus_bps,
+            timestamp,
+        );
+        vault.record_rate_change(
+            RATE_UTILIZATION_KINK_BONUS,
+            old_utilization_kink_bonus_bps,
+            utilization_kink_bonus_bps,
+            timestamp,
+        );
+
+        msg!(
+            "Vault utilization curve set to {} bps falling to {} bps at {} bps of {} tokens",
+            utilization_base_bonus_bps,
+            utilization_kink_bonus_bps,
+            utilization_kink_bps,
+            utilization_capacity
+        );
+        Ok(())
+    }
+
+    pub fn set_compound_split(ctx: Context<UpdateStake>, compound_split_bps: u16) -> Result<()> {
+        require!(
+            compound_split_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let user_stake = &mut ctx.accounts.user_stake;
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+
+        user_stake.set_compound_split(compound_split_bps);
+
+        msg!(
+            "User {} compounds {} bps of rewards across vaults",
+            user_stake.user,
+            compound_split_bps
+        );
+        Ok(())
+    }
+
+    pub fn set_dca_bonus(
+        ctx: Context<UpdateVault>,
+        dca_bonus_bps: u16,
+        max_dca_bonus_bps: u16,
+    ) -> Result<()> {
+        require!(
+            dca_bonus_bps as u64 <= BPS_DENOMINATOR && max_dca_bonus_bps as u64 <= BPS_DENOMINATOR,
+            VaultError::InvalidBasisPoints
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let old_dca_bonus_bps = vault.dca_bonus_bps;
+        let old_max_dca_bonus_bps = vault.max_dca_bonus_bps;
+        vault.set_dca_bonus(dca_bonus_bps, max_dca_bonus_bps);
+        vault.record_rate_change(RATE_DCA_BONUS, old_dca_bonus_bps, dca_bonus_bps, timestamp);
+        vault.record_rate_change(
+            RATE_MAX_DCA_BONUS,
+            old_max_dca_bonus_bps,
+            max_dca_bonus_bps,
+            timestamp,
+        );
+
+        msg!(
+            "Vault DCA bonus set to {} bps per on-time deposit, capped at {} bps",
+            dca_bonus_bps,
+            max_dca_bonus_bps
+        );
+        Ok(())
+    }
+
+    pub fn set_dca_schedule(
+        ctx: Context<UpdateStake>,
+        dca_amount: u64,
+        dca_interval: i64,
+    ) -> Result<()> {
+        require!(
+            dca_amount == 0 || dca_interval > 0,
+            VaultError::InvalidDcaSchedule
+        );
+
+        let user_stake = &mut ctx.accounts.user_stake;
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+
+        let clock = Clock::get()?;
+        ctx.accounts.vault.check_clock(&clock)?;
+        user_stake.set_dca_schedule(dca_amount, dca_interval, clock.unix_timestamp)?;
+
+        msg!(
+            "User {} scheduled a {} token deposit every {} seconds",
+            user_stake.user,
+            dca_amount,
+            dca_interval
+        );
+        Ok(())
+    }
+
+    pub fn keeper_deposit(ctx: Context<KeeperDeposit>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        vault.check_clock(&clock)?;
+        require!(!user_stake.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(user_stake.dca_amount > 0, VaultError::NoDcaSchedule);
+        require!(
+            clock.unix_timestamp >= user_stake.dca_next_time,
+            VaultError::DcaDepositNotDue
+        );
+        require!(
+            clock.unix_timestamp < user_stake.unlock_time,
+            VaultError::TargetStakeUnlocked
+        );
+
+        // The vault PDA pulls the deposit as the delegate the user approved
+        // on their token account.
+        let amount = user_stake.dca_amount;
+        transfer_from_vault(
+            vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        vault.accrue_management_fee(clock.unix_timestamp)?;
+        let reward_reserve = vault.reward_reserve(ctx.accounts.vault_token_account.amount);
+        vault.add_staked(amount)?;
+
+        let update_cohort = user_stake.in_cohort && vault.is_cohort_open();
+        if update_cohort {
+            vault.remove_cohort_member(user_stake)?;
+        }
+
+        user_stake.compound(amount)?;
+        user_stake.record_dca_deposit(clock.unix_timestamp)?;
+        let dca_bonus_bps = vault.calculate_dca_bonus_bps(user_stake.dca_deposits);
+        user_stake.set_dca_bonus(dca_bonus_bps);
+
+        let committed_reward = user_stake.calculate_committed_reward(vault)?;
+        vault.release_committed_reward(user_stake.committed_reward);
+        vault.check_reserve_buffer(reward_reserve, committed_reward)?;
+        vault.commit_reward(committed_reward)?;
+        user_stake.set_committed_reward(committed_reward);
+
+        if update_cohort {
+            user_stake.resize_cohort(clock.unix_timestamp);
+            vault.add_cohort_member(user_stake, clock.unix_timestamp)?;
+        }
+
+        if vault.emits(EVENT_STAKE) {
+            emit!(DcaDepositEvent {
+                user: user_stake.user,
+                keeper: ctx.accounts.keeper.key(),
+                amount,
+                dca_deposits: user_stake.dca_deposits,
+            });
+        }
+
+        msg!(
+            "Keeper deposited {} tokens into stake of {} ({} on-time deposits)",
+            amount,
+            user_stake.user,
+            user_stake.dca_deposits
+        );
+        Ok(())
+    }
+
+    pub fn get_next_unlock<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetNextUnlock>,
+        user: Pubkey,
+    ) -> Result<Option<NextUnlock>> {
+        find_next_unlock(ctx.remaining_accounts, user)
+    }
+}
+
+fn find_next_unlock<'info>(
+    stake_accounts: &'info [AccountInfo<'info>],
+    user: Pubkey,
+) -> Result<Option<NextUnlock>> {
+    let mut next_unlock: Option<NextUnlock> = None;
+
+    for account_info in stake_accounts.iter() {
+        let user_stake = Account::<UserStake>::try_from(account_info)?;
+        require_keys_eq!(user_stake.user, user, VaultError::UnauthorizedUser);
+
+        if user_stake.is_withdrawn {
+            continue;
+        }
+
+        let is_earlier = match &next_unlock {
+            Some(next) => user_stake.unlock_time < next.unlock_time,
+            None => true,
+        };
+
+        if is_earlier {
+            next_unlock = Some(NextUnlock {
+                user_stake: account_info.key(),
+                unlock_time: user_stake.unlock_time,
+                amount: user_stake.amount,
+            });
+        }
+    }
+
+    Ok(next_unlock)
+}
+
+struct Withdrawal<'a, 'info> {
+    vault: &'a mut Account<'info, Vault>,
+    user_stake: &'a mut Account<'info, UserStake>,
+    user_stats: &'a mut Account<'info, UserStats>,
+    user_stats_bump: u8,
+    user_profile: &'a mut Account<'info, UserProfile>,
+    user_profile_bump: u8,
+    user: Pubkey,
+    destination: &'a Account<'info, TokenAccount>,
+    vault_token_account: &'a Account<'info, TokenAccount>,
+    stake_decimals: u8,
+    loyalty_mint: &'a Option<Account<'info, Mint>>,
+    user_loyalty_token_account: &'a Option<Account<'info, TokenAccount>>,
+    reward_ledger: &'a mut Option<Account<'info, RewardLedger>>,
+    savings_vault: &'a mut Option<Account<'info, SavingsVault>>,
+    guardian: Option<Pubkey>,
+    keeper_token_account: Option<&'a Account<'info, TokenAccount>>,
+    defer_reward: bool,
+    token_program: &'a Program<'info, Token>,
+}
+
+fn check_withdrawal_gates(
+    vault: &Vault,
+    user_stake: &UserStake,
+    guardian: Option<Pubkey>,
+    clock: &Clock,
+) -> Result<()> {
+    vault.check_clock(clock)?;
+    user_stake.check_guardian(guardian)?;
+    user_stake.check_if_active(clock.unix_timestamp)?;
+    user_stake.check_if_unlocked(clock.unix_timestamp)?;
+    user_stake.check_not_same_slot(clock.slot)?;
+    user_stake.check_if_commitment_settled(clock.unix_timestamp)
+}
+
+fn process_withdrawal(withdrawal: Withdrawal) -> Result<u64> {
+    let Withdrawal {
+        vault,
+        user_stake,
+        user_stats,
+        user_stats_bump,
+        user_profile,
+        user_profile_bump,
+        user,
+        destination,
+        vault_token_account,
+        stake_decimals,
+        loyalty_mint,
+        user_loyalty_token_account,
+        reward_ledger,
+        savings_vault,
+        guardian,
+        keeper_token_account,
+        defer_reward,
+        token_program,
+    } = withdrawal;
+    let clock = Clock::get()?;
+
+    check_withdrawal_gates(vault, user_stake, guardian, &clock)?;
+
+    let failed_commitment = user_stake.has_failed_commitment();
+
+    let streak_bonus_bps = if user_stake.earns_bonus() {
+        user_stake.streak_bonus_bps as u64
+    } else {
+        0
+    };
+    let streak_bonus = user_stake.calculate_bonus(streak_bonus_bps)?;
+
+    user_profile.record_stake(user, user_profile_bump, user_stake.stake_time);
+
+    let bonus_bps = user_stake.calculate_withdrawal_bonus_bps(vault, clock.unix_timestamp);
+
+    let total_return = if failed_commitment {
+        let forfeited = user_stake.calculate_bonus(vault.commitment_forfeit_bps as u64)?;
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(CommitmentForfeitedEvent { user, forfeited });
+        }
+
+        user_stake
+            .amount
+            .checked_sub(forfeited)
+            .ok_or(VaultError::MathOverflow)?
+    } else {
+        let total_return =
+            user_stake.calculate_total_return(bonus_bps, vault, clock.unix_timestamp)?;
+
+        if bonus_bps > 0 && vault.emits(EVENT_WITHDRAW) {
+            emit_rounding(
+                user_stake,
+                vault,
+                user,
+                bonus_bps,
+                total_return - user_stake.amount,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        total_return
+    };
+
+    let reward = total_return.saturating_sub(user_stake.amount);
+
+    let payout = if defer_reward && user_stake.savings_lock_seconds > 0 && reward > 0 {
+        let savings_vault = savings_vault
+            .as_mut()
+            .ok_or(VaultError::SavingsAccountRequired)?;
+        savings_vault.deposit(
+            reward,
+            clock.unix_timestamp,
+            user_stake.savings_lock_seconds,
+        )?;
+        vault.add_outstanding_payout(reward)?;
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(SavingsDepositedEvent {
+                user,
+                amount: reward,
+                release_time: savings_vault.release_time,
+            });
+        }
+
+        user_stake.amount
+    } else if defer_reward && vault.installment_count > 0 && reward > 0 {
+        user_stake.schedule_installments(
+            reward,
+            vault.installment_count,
+            vault.installment_interval,
+            clock.unix_timestamp,
+        );
+        vault.add_outstanding_payout(reward)?;
+        user_stake.amount
+    } else {
+        total_return
+    };
+
+    let hold_seconds = vault.dispute_window_seconds.max(user_stake.unbond_period);
+    let held = hold_seconds > 0;
+
+    let keeper_tip = match keeper_token_account {
+        Some(keeper_token_account) if !held => {
+            let tip = vault.calculate_keeper_tip(payout.saturating_sub(user_stake.amount))?;
+
+            if tip > 0 {
+                transfer_from_vault(
+                    vault,
+                    vault_token_account,
+                    keeper_token_account,
+                    token_program,
+                    tip,
+                )?;
+            }
+            tip
+        }
+        _ => 0,
+    };
+    let payout = payout
+        .checked_sub(keeper_tip)
+        .ok_or(VaultError::MathOverflow)?;
+    let paid_reward = payout.saturating_sub(user_stake.amount);
+
+    if held {
+        let available_at = clock
+            .unix_timestamp
+            .checked_add(hold_seconds)
+            .ok_or(VaultError::MathOverflow)?;
+        user_stake.hold_payout(payout, paid_reward, destination.key(), available_at);
+        vault.add_outstanding_payout(payout)?;
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(WithdrawalHeldEvent {
+                user,
+                amount: payout,
+                available_at,
+            });
+        }
+    } else {
+        transfer_from_vault(
+            vault,
+            vault_token_account,
+            destination,
+            token_program,
+            payout,
+        )?;
+
+        record_reward(
+            vault,
+            reward_ledger,
+            user,
+            paid_reward,
+            clock.unix_timestamp,
+        )?;
+    }
+
+    vault.accrue_management_fee(clock.unix_timestamp)?;
+    vault.remove_staked(user_stake.amount)?;
+    vault.release_committed_reward(user_stake.committed_reward);
+    user_stake.set_committed_reward(0);
+
+    if user_stake.in_cohort && vault.is_cohort_open() {
+        vault.remove_cohort_member(user_stake)?;
+        vault.update_cohort_fill(clock.unix_timestamp);
+        user_stake.leave_cohort();
+    }
+
+    user_stake.mark_as_withdrawn();
+
+    if user_stake.stake_years > 0 {
+        if failed_commitment {
+            user_stats.break_streak(user, user_stats_bump);
+        } else {
+            user_stats.record_completed_stake(user, user_stats_bump);
+        }
+
+        if vault.emits(EVENT_WITHDRAW) {
+            emit!(StreakUpdatedEvent {
+                user,
+                current_streak: user_stats.current_streak,
+                streak_bonus,
+            });
+        }
+    }
+
+    if let Some(loyalty_mint_key) = vault.loyalty_mint {
+        let (Some(loyalty_mint), Some(user_loyalty_token_account)) =
+            (loyalty_mint, user_loyalty_token_account)
+        else {
+            return err!(VaultError::LoyaltyAccountsRequired);
+        };
+        require_keys_eq!(
+            loyalty_mint.key(),
+            loyalty_mint_key,
+            VaultError::InvalidLoyaltyMint
+        );
+
+        let loyalty_amount =
+            user_stake.calculate_loyalty_reward(stake_decimals, loyalty_mint.decimals)?;
+
+        if held {
+            user_stake.hold_loyalty(loyalty_amount);
+        } else {
+            mint_from_vault(
+                vault,
+                loyalty_mint,
+                user_loyalty_token_account,
+                token_program,
+                loyalty_amount,
+            )?;
+
+            if vault.emits(EVENT_WITHDRAW) {
+                emit!(LoyaltyMintedEvent {
+                    user,
+                    amount: loyalty_amount,
+                });
+            }
+        }
+    }
+
+    if vault.emits(EVENT_WITHDRAW) {
+        emit!(StakeWithdrawnEvent {
+            user,
+            original_amount: user_stake.amount,
+            total_return: payout,
+            multiplier: user_stake.get_multiplier(),
+        });
+    }
+
+    msg!(
+        "User withdrew {} tokens ({}x multiplier)",
+        payout,
+        user_stake.get_multiplier()
+    );
+    Ok(payout)
+}
+
+fn emit_rounding(
+    user_stake: &UserStake,
+    vault: &Vault,
+    user: Pubkey,
+    bonus_bps: u64,
+    paid_reward: u64,
+    current_time: i64,
+) -> Result<()> {
+    let exact_reward_scaled = user_stake.calculate_reward_scaled(bonus_bps, vault, current_time)?;
+
+    emit!(RoundingEvent {
+        user,
+        exact_reward_scaled,
+        paid_reward,
+        remainder_scaled: (exact_reward_scaled % BPS_DENOMINATOR as u128) as u64,
+        scale: BPS_DENOMINATOR,
+    });
+    Ok(())
+}
+
+fn record_reward(
+    vault: &Vault,
+    reward_ledger: &mut Option<Account<RewardLedger>>,
+    user: Pubkey,
+    amount: u64,
+    timestamp: i64,
+) -> Result<()> {
+    if vault.reward_ledger.is_none() || amount == 0 {
+        return Ok(());
+    }
+
+    let reward_ledger = reward_ledger
+        .as_mut()
+        .ok_or(VaultError::RewardLedgerRequired)?;
+
+    reward_ledger.record(RewardLedgerEntry {
+        user,
+        amount,
+        timestamp,
+    });
+    Ok(())
+}
+
+fn split_by_weights(total_amount: u64, weights_bps: &[u16]) -> Result<Vec<u64>> {
+    let weight_sum: u64 = weights_bps.iter().map(|weight| *weight as u64).sum();
+    require!(
+        weight_sum == BPS_DENOMINATOR,
+        VaultError::InvalidAllocationWeights
+    );
+
+    let mut amounts = Vec::with_capacity(weights_bps.len());
+    let mut distributed: u64 = 0;
+
+    for (i, weight) in weights_bps.iter().enumerate() {
+        let amount = if i == weights_bps.len() - 1 {
+            total_amount - distributed
+        } else {
+            let share = (total_amount as u128)
+                .checked_mul(*weight as u128)
+                .ok_or(VaultError::MathOverflow)?
+                / BPS_DENOMINATOR as u128;
+            u64::try_from(share).map_err(|_| VaultError::MathOverflow)?
+        };
+
+        distributed = distributed
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        amounts.push(amount);
+    }
+
+    Ok(amounts)
+}
+
+fn check_same_mint(
+    user_token_account: &TokenAccount,
+    vault_token_account: &TokenAccount,
+) -> Result<()> {
+    require_keys_eq!(
+        user_token_account.mint,
+        vault_token_account.mint,
+        VaultError::MintMismatch
+    );
+    Ok(())
+}
+
+fn transfer_from_vault<'info>(
+    vault: &Account<'info, Vault>,
+    from: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let admin_key = vault.admin;
+    let vault_bump = vault.bump;
+    let seeds = &[b"vault", admin_key.as_ref(), &[vault_bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: from.to_account_info(),
+        to: to.to_account_info(),
+        authority: vault.to_account_info(),
+    };
+
+    let cpi_ctx =
+        CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+    token::transfer(cpi_ctx, amount)
+}
+
+fn mint_from_vault<'info>(
+    vault: &Account<'info, Vault>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let admin_key = vault.admin;
+    let vault_bump = vault.bump;
+    let seeds = &[b"vault", admin_key.as_ref(), &[vault_bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    let cpi_accounts = MintTo {
+        mint: mint.to_account_info(),
+        to: to.to_account_info(),
+        authority: vault.to_account_info(),
+    };
+
+    let cpi_ctx =
+        CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+    token::mint_to(cpi_ctx, amount)
+}
+
+#[derive(Accounts)]
+pub struct CreateVault<'info> {
+    #[account(
+        init,                                       
+        payer = admin,                                
+        space = 8 + Vault::INIT_SPACE,                    
+        seeds = [b"vault", admin.key().as_ref()],      
+        bump                                           
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: only its owning program is checked; Token-2022 mints can rebase or accrue interest
+    #[account(owner = token::ID @ VaultError::RebasingMintUnsupported)]
+    pub mint: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],     
+        bump = vault.bump                              
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,                                         
+        payer = user,                                   
+        space = 8 + UserStake::INIT_SPACE,          
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],  
+        bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"user_stats", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserProfile::INIT_SPACE,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        mut,                                         
+        associated_token::mint = mint,             
+        associated_token::authority = user        
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,                                 
+        payer = user,                                  
+        associated_token::mint = mint,           
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,                                         
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"user_stats", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserProfile::INIT_SPACE,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        init_if_needed,                                 
+        payer = user,                                  
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,                                       
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub loyalty_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = loyalty_mint,
+        token::authority = user
+    )]
+    pub user_loyalty_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    #[account(
+        mut,
+        seeds = [b"savings", vault.key().as_ref(), user.key().as_ref()],
+        bump = savings_vault.bump
+    )]
+    pub savings_vault: Option<Account<'info, SavingsVault>>,
+
+    pub guardian: Option<Signer<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RescueWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"user_stats", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserProfile::INIT_SPACE,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub loyalty_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = loyalty_mint,
+        token::authority = user
+    )]
+    pub user_loyalty_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    #[account(
+        mut,
+        seeds = [b"savings", vault.key().as_ref(), user.key().as_ref()],
+        bump = savings_vault.bump
+    )]
+    pub savings_vault: Option<Account<'info, SavingsVault>>,
+
+    pub guardian: Option<Signer<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimManagementFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = mint,
+        associated_token::authority = admin
+    )]
+    pub admin_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetNextUnlock {}
+
+#[derive(Accounts)]
+pub struct AdminUpdateStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump = user_stake.bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub admin: Signer<'info>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStake<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAsBeneficiary<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump = user_stake.bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"user_stats", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        space = 8 + UserProfile::INIT_SPACE,
+        seeds = [b"user_profile", user_stake.user.as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        init_if_needed,
+        payer = beneficiary,
+        associated_token::mint = mint,
+        associated_token::authority = beneficiary
+    )]
+    pub beneficiary_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub loyalty_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = loyalty_mint,
+        token::authority = beneficiary
+    )]
+    pub beneficiary_loyalty_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    pub guardian: Option<Signer<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetLoyaltyMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mint::authority = vault)]
+    pub loyalty_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct EmitMaturityWarning<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump = user_stake.bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+}
+
+#[derive(Accounts)]
+pub struct CreateRewardLedger<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + RewardLedger::INIT_SPACE,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump
+    )]
+    pub reward_ledger: Account<'info, RewardLedger>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundAcross<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", source_vault.admin.as_ref()],
+        bump = source_vault.bump
+    )]
+    pub source_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", source_vault.key().as_ref(), user.key().as_ref()],
+        bump = source_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub source_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = source_vault
+    )]
+    pub source_vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", target_vault.admin.as_ref()],
+        bump = target_vault.bump,
+        constraint = target_vault.key() != source_vault.key() @ VaultError::SameStakeCompound
+    )]
+    pub target_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", target_vault.key().as_ref(), user.key().as_ref()],
+        bump = target_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub target_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = target_vault
+    )]
+    pub target_vault_token_account: Account<'info, TokenAccount>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub guardian: Option<Signer<'info>>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", source_vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyStakeCollateral<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"user_stake", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump = user_stake.bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeRewards<'info> {
+    pub funder: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = funder
+    )]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReserveReport<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct SetSavingsOption<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + SavingsVault::INIT_SPACE,
+        seeds = [b"savings", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub savings_vault: Account<'info, SavingsVault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseSavings<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"savings", vault.key().as_ref(), user.key().as_ref()],
+        bump = savings_vault.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub savings_vault: Account<'info, SavingsVault>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct GuardedUpdateStake<'info> {
+    #[account(
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub user: Signer<'info>,
+
+    pub guardian: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct StartMerkleCampaign<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + MerkleCampaign::INIT_SPACE,
+        seeds = [b"merkle_campaign", vault.key().as_ref()],
+        bump
+    )]
+    pub merkle_campaign: Account<'info, MerkleCampaign>,
+
+    #[account(
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimMerkleBonus<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"merkle_campaign", vault.key().as_ref()],
+        bump = merkle_campaign.bump
+    )]
+    pub merkle_campaign: Account<'info, MerkleCampaign>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump,
+        has_one = admin @ VaultError::UnauthorizedAdmin
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    /// CHECK: only used as the owner of the stake and its refund token account
+    pub user: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stats", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user_stake.user.as_ref()],
+        bump = user_stake.bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        address = user_stake.payout_destination @ VaultError::InvalidPayoutDestination
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    #[account(mut)]
+    pub loyalty_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = loyalty_mint,
+        constraint = loyalty_token_account.owner == destination_token_account.owner
+            @ VaultError::InvalidPayoutDestination
+    )]
+    pub loyalty_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct KeeperWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    /// CHECK: only used as the owner of the stake and its destination token account
+    pub user: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = 8 + UserStats::INIT_SPACE,
+        seeds = [b"user_stats", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = 8 + UserProfile::INIT_SPACE,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = keeper
+    )]
+    pub keeper_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub loyalty_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = loyalty_mint,
+        token::authority = user
+    )]
+    pub user_loyalty_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"reward_ledger", vault.key().as_ref()],
+        bump = reward_ledger.bump
+    )]
+    pub reward_ledger: Option<Account<'info, RewardLedger>>,
+
+    #[account(
+        mut,
+        seeds = [b"savings", vault.key().as_ref(), user.key().as_ref()],
+        bump = savings_vault.bump
+    )]
+    pub savings_vault: Option<Account<'info, SavingsVault>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + ReserveStake::INIT_SPACE,
+        seeds = [b"reserve_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub reserve_stake: Account<'info, ReserveStake>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reserve_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = reserve_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub reserve_stake: Account<'info, ReserveStake>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetUnbondPeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub user: Signer<'info>,
+
+    #[account(
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct KeeperDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.admin.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", vault.key().as_ref(), user.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = user @ VaultError::UnauthorizedUser
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    /// CHECK: only used as the owner of the stake and its source token account
+    pub user: UncheckedAccount<'info>,
+
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Vault {
+    pub admin: Pubkey,
+    pub bump: u8,
+    pub loyalty_mint: Option<Pubkey>,
+    pub installment_count: u8,
+    pub installment_interval: i64,
+    pub streak_bonus_bps: u16,
+    pub long_lock_threshold: u8,
+    pub commitment_forfeit_bps: u16,
+    pub total_staked: u64,
+    pub management_fee_bps: u16,
+    pub accrued_management_fee: u64,
+    pub last_fee_accrual: i64,
+    pub max_reward_per_stake: u64,
+    pub cohort_reward_pool: u64,
+    pub cohort_total_weight: u128,
+    pub cohort_closed_at: i64,
+    pub event_flags: u32,
+    pub settlement_delay_seconds: i64,
+    pub reward_ramp_seconds: i64,
+    pub reward_ledger: Option<Pubkey>,
+    pub epoch_start: i64,
+    pub epoch_end: i64,
+    pub epoch_min_stake: u64,
+    pub epoch_bonus_bps: u16,
+    pub max_lock_bonus_bps: u16,
+    pub allow_flexible: bool,
+    pub claim_window_seconds: i64,
+    pub reward_decay_seconds: i64,
+    pub veteran_bonus_bps: u16,
+    pub congestion_start: i64,
+    pub congestion_end: i64,
+    pub congestion_bonus_bps: u16,
+    pub dispute_window_seconds: i64,
+    pub one_year_min_amount: u64,
+    pub two_year_min_amount: u64,
+    pub keeper_tip_bps: u16,
+    pub cohort_subscribed: u64,
+    pub cohort_target: u64,
+    pub cohort_deadline: i64,
+    pub cohort_filled_at: i64,
+    pub full_subscription_bonus_bps: u16,
+    pub clock_tolerance_seconds: i64,
+    pub clock_anchor_slot: u64,
+    pub clock_anchor_time: i64,
+    pub curve_start_bonus_bps: u16,
+    pub curve_span: u64,
+    pub curve_deposited: u64,
+    pub committed_rewards: u64,
+    pub reserve_buffer_bps: u16,
+    pub cohort_weight_offset: i128,
+    pub outstanding_payouts: u64,
+    pub clock_max_drift_bps: u16,
+    pub max_streak_bonus_bps: u16,
+    pub max_veteran_bonus_bps: u16,
+    pub reserve_staked: u64,
+    pub reserve_shares: u64,
+    pub reserve_epoch: u32,
+    pub reserve_fee_share_bps: u16,
+    pub rate_history_next: u16,
+    pub rate_history_total: u64,
+    #[max_len(RATE_HISTORY_CAPACITY)]
+    pub rate_history: Vec<RateChange>,
+    pub fee_to_reserve_bps: u16,
+    pub max_unbond_period: i64,
+    pub long_unbond_bonus_bps: u16,
+    pub utilization_capacity: u64,
+    pub utilization_kink_bps: u16,
+    pub utilization_base_bonus_bps: u16,
+    pub utilization_kink_bonus_bps: u16,
+    pub dca_bonus_bps: u16,
+    pub max_dca_bonus_bps: u16,
+}
+
+impl Vault {
+    pub fn initialize(&mut self, admin: Pubkey, bump: u8, current_time: i64) {
+        self.admin = admin;
+        self.bump = bump;
+        self.loyalty_mint = None;
+        self.installment_count = 0;
+        self.installment_interval = 0;
+        self.streak_bonus_bps = 0;
+        self.long_lock_threshold = 0;
+        self.commitment_forfeit_bps = 0;
+        self.total_staked = 0;
+        self.management_fee_bps = 0;
+        self.accrued_management_fee = 0;
+        self.last_fee_accrual = current_time;
+        self.max_reward_per_stake = 0;
+        self.cohort_reward_pool = 0;
+        self.cohort_total_weight = 0;
+        self.cohort_closed_at = 0;
+        self.event_flags = EVENT_ALL;
+        self.settlement_delay_seconds = 0;
+        self.reward_ramp_seconds = 0;
+        self.reward_ledger = None;
+        self.epoch_start = 0;
+        self.epoch_end = 0;
+        self.epoch_min_stake = 0;
+        self.epoch_bonus_bps = 0;
+        self.max_lock_bonus_bps = 0;
+        self.allow_flexible = false;
+        self.claim_window_seconds = 0;
+        self.reward_decay_seconds = 0;
+        self.veteran_bonus_bps = 0;
+        self.congestion_start = 0;
+        self.congestion_end = 0;
+        self.congestion_bonus_bps = 0;
+        self.dispute_window_seconds = 0;
+        self.one_year_min_amount = 0;
+        self.two_year_min_amount = 0;
+        self.keeper_tip_bps = 0;
+        self.cohort_subscribed = 0;
+        self.cohort_target = 0;
+        self.cohort_deadline = 0;
+        self.cohort_filled_at = 0;
+        self.full_subscription_bonus_bps = 0;
+        self.clock_tolerance_seconds = 0;
+        self.clock_anchor_slot = 0;
+        self.clock_anchor_time = 0;
+        self.curve_start_bonus_bps = 0;
+        self.curve_span = 0;
+        self.curve_deposited = 0;
+        self.committed_rewards = 0;
+        self.reserve_buffer_bps = 0;
+        self.cohort_weight_offset = 0;
+        self.outstanding_payouts = 0;
+        self.clock_max_drift_bps = 0;
+        self.max_streak_bonus_bps = 0;
+        self.max_veteran_bonus_bps = 0;
+        self.reserve_staked = 0;
+        self.reserve_shares = 0;
+        self.reserve_epoch = 0;
+        self.reserve_fee_share_bps = 0;
+        self.rate_history_next = 0;
+        self.rate_history_total = 0;
+        self.rate_history = Vec::new();
+        self.fee_to_reserve_bps = 0;
+        self.max_unbond_period = 0;
+        self.long_unbond_bonus_bps = 0;
+        self.utilization_capacity = 0;
+        self.utilization_kink_bps = 0;
+        self.utilization_base_bonus_bps = 0;
+        self.utilization_kink_bonus_bps = 0;
+        self.dca_bonus_bps = 0;
+        self.max_dca_bonus_bps = 0;
+    }
+
+    pub fn set_loyalty_mint(&mut self, loyalty_mint: Pubkey) {
+        self.loyalty_mint = Some(loyalty_mint);
+    }
+
+    pub fn set_installment_schedule(&mut self, installment_count: u8, installment_interval: i64) {
+        self.installment_count = installment_count;
+        self.installment_interval = installment_interval;
+    }
+
+    pub fn set_streak_bonus(&mut self, streak_bonus_bps: u16, max_streak_bonus_bps: u16) {
+        self.streak_bonus_bps = streak_bonus_bps;
+        self.max_streak_bonus_bps = max_streak_bonus_bps;
+    }
+
+    pub fn calculate_streak_bonus_bps(&self, current_streak: u16) -> u16 {
+        (self.streak_bonus_bps as u64)
+            .saturating_mul(current_streak as u64)
+            .min(self.max_streak_bonus_bps as u64) as u16
+    }
+
+    pub fn set_long_lock_threshold(&mut self, long_lock_threshold: u8) {
+        self.long_lock_threshold = long_lock_threshold;
+    }
+
+    pub fn is_long_lock(&self, stake_years: u8) -> bool {
+        self.long_lock_threshold > 0 && stake_years > self.long_lock_threshold
+    }
+
+    pub fn set_commitment_forfeit(&mut self, commitment_forfeit_bps: u16) {
+        self.commitment_forfeit_bps = commitment_forfeit_bps;
+    }
+
+    pub fn add_staked(&mut self, amount: u64) -> Result<()> {
+        self.total_staked = self
+            .total_staked
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn remove_staked(&mut self, amount: u64) -> Result<()> {
+        self.total_staked = self
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn set_management_fee(&mut self, management_fee_bps: u16) {
+        self.management_fee_bps = management_fee_bps;
+    }
+
+    pub fn accrue_management_fee(&mut self, current_time: i64) -> Result<()> {
+        let elapsed = current_time.saturating_sub(self.last_fee_accrual);
+
+        if elapsed > 0 {
+            let fee = (self.total_staked as u128)
+                .checked_mul(self.management_fee_bps as u128)
+                .and_then(|value| value.checked_mul(elapsed as u128))
+                .ok_or(VaultError::MathOverflow)?
+                / (BPS_DENOMINATOR as u128 * SECONDS_PER_YEAR as u128);
+
+            let fee = u64::try_from(fee).map_err(|_| VaultError::MathOverflow)?;
+            self.accrued_management_fee = self
+                .accrued_management_fee
+                .checked_add(fee)
+                .ok_or(VaultError::MathOverflow)?;
+            self.last_fee_accrual = current_time;
+        }
+
+        Ok(())
+    }
+
+    pub fn claimable_management_fee(&self, vault_balance: u64) -> u64 {
+        let surplus = vault_balance
+            .saturating_sub(self.total_staked)
+            .saturating_sub(self.outstanding_payouts)
+            .saturating_sub(self.required_reserve())
+            .saturating_sub(self.reserve_staked);
+
+        self.accrued_management_fee.min(surplus)
+    }
+
+    pub fn required_reserve(&self) -> u64 {
+        let buffered = (self.committed_rewards as u128) * (self.reserve_buffer_bps as u128)
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(buffered)
+            .unwrap_or(u64::MAX)
+            .max(self.committed_rewards)
+    }
+
+    pub fn deduct_management_fee(&mut self, amount: u64) {
+        self.accrued_management_fee = self.accrued_management_fee.saturating_sub(amount);
+    }
+
+    pub fn set_max_reward_per_stake(&mut self, max_reward_per_stake: u64) {
+        self.max_reward_per_stake = max_reward_per_stake;
+    }
+
+    pub fn set_event_flags(&mut self, event_flags: u32) {
+        self.event_flags = event_flags;
+    }
+
+    pub fn emits(&self, flag: u32) -> bool {
+        self.event_flags & flag != 0
+    }
+
+    pub fn set_settlement_delay(&mut self, settlement_delay_seconds: i64) {
+        self.settlement_delay_seconds = settlement_delay_seconds;
+    }
+
+    pub fn set_reward_ramp(&mut self, reward_ramp_seconds: i64) {
+        self.reward_ramp_seconds = reward_ramp_seconds;
+    }
+
+    pub fn set_reward_ledger(&mut self, reward_ledger: Pubkey) {
+        self.reward_ledger = Some(reward_ledger);
+    }
+
+    pub fn set_loyalty_epoch(
+        &mut self,
+        epoch_start: i64,
+        epoch_end: i64,
+        epoch_min_stake: u64,
+        epoch_bonus_bps: u16,
+    ) {
+        self.epoch_start = epoch_start;
+        self.epoch_end = epoch_end;
+        self.epoch_min_stake = epoch_min_stake;
+        self.epoch_bonus_bps = epoch_bonus_bps;
+    }
+
+    pub fn set_max_lock_bonus(&mut self, max_lock_bonus_bps: u16) {
+        self.max_lock_bonus_bps = max_lock_bonus_bps;
+    }
+
+    pub fn max_lock_bonus_for(&self, stake_years: u8) -> u16 {
+        if stake_years == MAX_STAKE_YEARS {
+            self.max_lock_bonus_bps
+        } else {
+            0
+        }
+    }
+
+    pub fn set_allow_flexible(&mut self, allow_flexible: bool) {
+        self.allow_flexible = allow_flexible;
+    }
+
+    pub fn set_reward_decay(&mut self, claim_window_seconds: i64, reward_decay_seconds: i64) {
+        self.claim_window_seconds = claim_window_seconds;
+        self.reward_decay_seconds = reward_decay_seconds;
+    }
+
+    pub fn set_veteran_bonus(&mut self, veteran_bonus_bps: u16, max_veteran_bonus_bps: u16) {
+        self.veteran_bonus_bps = veteran_bonus_bps;
+        self.max_veteran_bonus_bps = max_veteran_bonus_bps;
+    }
+
+    pub fn calculate_veteran_bonus_bps(&self, tenure: i64) -> u64 {
+        (self.veteran_bonus_bps as u64)
+            .saturating_mul((tenure / SECONDS_PER_YEAR) as u64)
+            .min(self.max_veteran_bonus_bps as u64)
+    }
+
+    pub fn activate_congestion_bonus(
+        &mut self,
+        current_time: i64,
+        duration: i64,
+        congestion_bonus_bps: u16,
+    ) -> Result<()> {
+        self.congestion_start = current_time;
+        self.congestion_end = current_time
+            .checked_add(duration)
+            .ok_or(VaultError::MathOverflow)?;
+        self.congestion_bonus_bps = congestion_bonus_bps;
+        Ok(())
+    }
+
+    pub fn set_dispute_window(&mut self, dispute_window_seconds: i64) {
+        self.dispute_window_seconds = dispute_window_seconds;
+    }
+
+    pub fn set_min_lock_for_amount(&mut self, one_year_min_amount: u64, two_year_min_amount: u64) {
+        self.one_year_min_amount = one_year_min_amount;
+        self.two_year_min_amount = two_year_min_amount;
+    }
+
+    pub fn min_stake_years_for(&self, amount: u64) -> u8 {
+        if self.two_year_min_amount > 0 && amount >= self.two_year_min_amount {
+            2
+        } else if self.one_year_min_amount > 0 && amount >= self.one_year_min_amount {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn set_keeper_tip(&mut self, keeper_tip_bps: u16) {
+        self.keeper_tip_bps = keeper_tip_bps;
+    }
+
+    pub fn calculate_keeper_tip(&self, reward: u64) -> Result<u64> {
+        let tip = (reward as u128)
+            .checked_mul(self.keeper_tip_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(tip).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn set_clock_tolerance(
+        &mut self,
+        clock_tolerance_seconds: i64,
+        clock_max_drift_bps: u16,
+        clock: &Clock,
+    ) {
+        self.clock_tolerance_seconds = clock_tolerance_seconds;
+        self.clock_max_drift_bps = clock_max_drift_bps;
+        self.clock_anchor_slot = clock.slot;
+        self.clock_anchor_time = clock.unix_timestamp;
+    }
+
+    pub fn check_clock(&self, clock: &Clock) -> Result<()> {
+        if self.clock_tolerance_seconds == 0 {
+            return Ok(());
+        }
+
+        let elapsed_slots = clock.slot.saturating_sub(self.clock_anchor_slot);
+        let expected_elapsed = (elapsed_slots as i128) * (MS_PER_SLOT as i128) / 1000;
+        let actual_elapsed = (clock.unix_timestamp as i128) - (self.clock_anchor_time as i128);
+        let allowed_drift = (self.clock_tolerance_seconds as i128)
+            + expected_elapsed * (self.clock_max_drift_bps as i128) / (BPS_DENOMINATOR as i128);
+
+        require!(
+            (actual_elapsed - expected_elapsed).abs() <= allowed_drift,
+            VaultError::ClockUnreliable
+        );
+        Ok(())
+    }
+
+    pub fn set_bonding_curve(&mut self, curve_start_bonus_bps: u16, curve_span: u64) {
+        self.curve_start_bonus_bps = curve_start_bonus_bps;
+        self.curve_span = curve_span;
+    }
+
+    pub fn calculate_curve_bonus_bps(&self, amount: u64) -> u16 {
+        if self.curve_start_bonus_bps == 0 || self.curve_span == 0 || amount == 0 {
+            return 0;
+        }
+
+        let span = self.curve_span as u128;
+        let start = (self.curve_deposited as u128).min(span);
+        let end = (self.curve_deposited as u128 + amount as u128).min(span);
+
+        let midpoint_bps =
+            (self.curve_start_bonus_bps as u128) * (2 * span - start - end) / (2 * span);
+        (midpoint_bps * (end - start) / amount as u128) as u16
+    }
+
+    pub fn apply_bonding_curve(&mut self, user_stake: &mut UserStake) -> Result<()> {
+        if user_stake.stake_years == 0 {
+            return Ok(());
+        }
+
+        user_stake.set_curve_bonus(self.calculate_curve_bonus_bps(user_stake.amount));
+        self.record_curve_deposit(user_stake.amount)
+    }
+
+    pub fn record_curve_deposit(&mut self, amount: u64) -> Result<()> {
+        self.curve_deposited = self
+            .curve_deposited
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn reward_reserve(&self, vault_balance: u64) -> u64 {
+        vault_balance
+            .saturating_sub(self.total_staked)
+            .saturating_sub(self.accrued_management_fee)
+            .saturating_sub(self.outstanding_payouts)
+    }
+
+    pub fn add_outstanding_payout(&mut self, amount: u64) -> Result<()> {
+        self.outstanding_payouts = self
+            .outstanding_payouts
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn fund_outstanding_payout(&mut self, vault_balance: u64, amount: u64) -> Result<()> {
+        let free_reserve = self
+            .reward_reserve(vault_balance)
+            .saturating_sub(self.committed_rewards);
+
+        require!(
+            free_reserve >= amount,
+            VaultError::InsufficientRewardReserve
+        );
+        self.add_outstanding_payout(amount)
+    }
+
+    pub fn settle_outstanding_payout(&mut self, amount: u64) {
+        self.outstanding_payouts = self.outstanding_payouts.saturating_sub(amount);
+    }
+
+    pub fn reserve_snapshot(&self, reserve_balance: u64, clock: &Clock) -> Result<ReserveSnapshot> {
+        let reward_reserve = self.reward_reserve(reserve_balance);
+
+        let liabilities = (self.total_staked as u128)
+            + (self.accrued_management_fee as u128)
+            + (self.outstanding_payouts as u128)
+            + (self.committed_rewards as u128);
+
+        let collateralization_bps = (reserve_balance as u128)
+            .checked_mul(BPS_DENOMINATOR as u128)
+            .ok_or(VaultError::MathOverflow)?
+            .checked_div(liabilities)
+            .map_or(u64::MAX, |ratio| u64::try_from(ratio).unwrap_or(u64::MAX));
+
+        Ok(ReserveSnapshot {
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            principal_held: self.total_staked,
+            accrued_management_fee: self.accrued_management_fee,
+            reserve_balance,
+            reward_reserve,
+            collateralization_bps,
+            committed_rewards: self.committed_rewards,
+            outstanding_payouts: self.outstanding_payouts,
+            insurance_balance: reward_reserve.saturating_sub(self.committed_rewards),
+        })
+    }
+
+    pub fn set_reserve_buffer(&mut self, reserve_buffer_bps: u16) {
+        self.reserve_buffer_bps = reserve_buffer_bps;
+    }
+
+    pub fn check_reserve_buffer(&self, reward_reserve: u64, new_commitment: u64) -> Result<()> {
+        if self.reserve_buffer_bps == 0 {
+            return Ok(());
+        }
+
+        let committed = (self.committed_rewards as u128)
+            .checked_add(new_commitment as u128)
+            .ok_or(VaultError::MathOverflow)?;
+
+        require!(
+            (reward_reserve as u128) * (BPS_DENOMINATOR as u128)
+                >= committed * (self.reserve_buffer_bps as u128),
+            VaultError::InsufficientReserveBuffer
+        );
+        Ok(())
+    }
+
+    pub fn commit_reward(&mut self, reward: u64) -> Result<()> {
+        self.committed_rewards = self
+            .committed_rewards
+            .checked_add(reward)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn release_committed_reward(&mut self, reward: u64) {
+        self.committed_rewards = self.committed_rewards.saturating_sub(reward);
+    }
+
+    pub fn set_reserve_fee_share(&mut self, reserve_fee_share_bps: u16) {
+        self.reserve_fee_share_bps = reserve_fee_share_bps;
+    }
+
+    pub fn record_rate_change(&mut self, rate: u8, old_bps: u16, new_bps: u16, timestamp: i64) {
+        if old_bps == new_bps {
+            return;
+        }
+
+        let entry = RateChange {
+            rate,
+            timestamp,
+            old_bps,
+            new_bps,
+        };
+        let index = self.rate_history_next as usize;
+
+        if self.rate_history.len() < RATE_HISTORY_CAPACITY {
+            self.rate_history.push(entry);
+        } else {
+            self.rate_history[index] = entry;
+        }
+
+        self.rate_history_next = ((index + 1) % RATE_HISTORY_CAPACITY) as u16;
+        self.rate_history_total = self.rate_history_total.saturating_add(1);
+    }
+
+    // Reserve stakes sit inside the reward reserve. Payouts draw on the
+    // admin-funded part first; once the reserve falls below the staked
+    // amount, the difference is written off against reserve stakers before
+    // any principal is touched.
+    pub fn sync_reserve_stake(&mut self, vault_balance: u64) {
+        self.reserve_staked = self.reserve_staked.min(self.reward_reserve(vault_balance));
+    }
+
+    pub fn add_reserve_stake(&mut self, amount: u64) -> Result<u64> {
+        if self.reserve_staked == 0 && self.reserve_shares > 0 {
+            // Every earlier reserve share was wiped out; start a new epoch so
+            // they cannot claim any part of the new deposit.
+            self.reserve_epoch = self
+                .reserve_epoch
+                .checked_add(1)
+                .ok_or(VaultError::MathOverflow)?;
+            self.reserve_shares = 0;
+        }
+
+        let shares = if self.reserve_shares == 0 {
+            amount
+        } else {
+            let shares = (amount as u128)
+                .checked_mul(self.reserve_shares as u128)
+                .ok_or(VaultError::MathOverflow)?
+                / (self.reserve_staked as u128);
+            u64::try_from(shares).map_err(|_| VaultError::MathOverflow)?
+        };
+        require!(shares > 0, VaultError::InvalidAmount);
+
+        self.reserve_staked = self
+            .reserve_staked
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        self.reserve_shares = self
+            .reserve_shares
+            .checked_add(shares)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(shares)
+    }
+
+    pub fn reserve_stake_value(&self, shares: u64) -> Result<u64> {
+        if self.reserve_shares == 0 {
+            return Ok(0);
+        }
+
+        let value = (shares as u128)
+            .checked_mul(self.reserve_staked as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / (self.reserve_shares as u128);
+        u64::try_from(value).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn remove_reserve_stake(&mut self, shares: u64, amount: u64) -> Result<()> {
+        self.reserve_shares = self
+            .reserve_shares
+            .checked_sub(shares)
+            .ok_or(VaultError::MathOverflow)?;
+        self.reserve_staked = self
+            .reserve_staked
+            .checked_sub(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn calculate_reserve_fee_share(&self, fee: u64) -> Result<u64> {
+        if self.reserve_shares == 0 {
+            return Ok(0);
+        }
+
+        let share = (fee as u128)
+            .checked_mul(self.reserve_fee_share_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / (BPS_DENOMINATOR as u128);
+        u64::try_from(share).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn set_fee_to_reserve(&mut self, fee_to_reserve_bps: u16) {
+        self.fee_to_reserve_bps = fee_to_reserve_bps;
+    }
+
+    pub fn set_unbond_bonus(&mut self, max_unbond_period: i64, long_unbond_bonus_bps: u16) {
+        self.max_unbond_period = max_unbond_period;
+        self.long_unbond_bonus_bps = long_unbond_bonus_bps;
+    }
+
+    pub fn calculate_unbond_bonus_bps(&self, unbond_period: i64) -> u16 {
+        if self.max_unbond_period <= 0 {
+            return 0;
+        }
+
+        ((self.long_unbond_bonus_bps as i128) * (unbond_period as i128)
+            / (self.max_unbond_period as i128)) as u16
+    }
+
+    pub fn set_utilization_curve(
+        &mut self,
+        utilization_capacity: u64,
+        utilization_kink_bps: u16,
+        utilization_base_bonus_bps: u16,
+        utilization_kink_bonus_bps: u16,
+    ) {
+        self.utilization_capacity = utilization_capacity;
+        self.utilization_kink_bps = utilization_kink_bps;
+        self.utilization_base_bonus_bps = utilization_base_bonus_bps;
+        self.utilization_kink_bonus_bps = utilization_kink_bonus_bps;
+    }
+
+    pub fn utilization_bps(&self) -> u64 {
+        if self.utilization_capacity == 0 {
+            return 0;
+        }
+
+        let utilization = (self.total_staked as u128) * (BPS_DENOMINATOR as u128)
+            / (self.utilization_capacity as u128);
+        utilization.min(BPS_DENOMINATOR as u128) as u64
+    }
+
+    // Piecewise linear: base bonus at 0% utilization, kink bonus at the kink,
+    // zero once the vault is at capacity.
+    pub fn calculate_utilization_bonus_bps(&self) -> u16 {
+        if self.utilization_base_bonus_bps == 0 {
+            return 0;
+        }
+
+        let utilization = self.utilization_bps();
+        let kink = self.utilization_kink_bps as u64;
+        let base = self.utilization_base_bonus_bps as u64;
+        let at_kink = self.utilization_kink_bonus_bps as u64;
+
+        let bonus = if utilization <= kink {
+            base - (base - at_kink) * utilization / kink
+        } else {
+            at_kink * (BPS_DENOMINATOR - utilization) / (BPS_DENOMINATOR - kink)
+        };
+        bonus as u16
+    }
+
+    pub fn set_dca_bonus(&mut self, dca_bonus_bps: u16, max_dca_bonus_bps: u16) {
+        self.dca_bonus_bps = dca_bonus_bps;
+        self.max_dca_bonus_bps = max_dca_bonus_bps;
+    }
+
+    pub fn calculate_dca_bonus_bps(&self, dca_deposits: u16) -> u16 {
+        (self.dca_bonus_bps as u64)
+            .saturating_mul(dca_deposits as u64)
+            .min(self.max_dca_bonus_bps as u64) as u16
+    }
+
+    pub fn calculate_fee_to_reserve(&self, fee: u64) -> Result<u64> {
+        let to_reserve = (fee as u128)
+            .checked_mul(self.fee_to_reserve_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / (BPS_DENOMINATOR as u128);
+        u64::try_from(to_reserve).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn share_fee_with_reserve_stakers(&mut self, amount: u64) -> Result<()> {
+        self.reserve_staked = self
+            .reserve_staked
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn is_valid_stake_period(&self, stake_years: u8) -> bool {
+        (1..=MAX_STAKE_YEARS).contains(&stake_years) || (stake_years == 0 && self.allow_flexible)
+    }
+
+    pub fn is_cohort_open(&self) -> bool {
+        self.cohort_closed_at == 0
+    }
+
+    pub fn add_cohort_member(&mut self, user_stake: &UserStake, current_time: i64) -> Result<()> {
+        self.cohort_subscribed = self
+            .cohort_subscribed
+            .checked_add(user_stake.cohort_amount)
+            .ok_or(VaultError::MathOverflow)?;
+        self.cohort_weight_offset = self
+            .cohort_weight_offset
+            .checked_add(user_stake.cohort_offset)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.update_cohort_fill(current_time);
+        Ok(())
+    }
+
+    pub fn remove_cohort_member(&mut self, user_stake: &UserStake) -> Result<()> {
+        self.cohort_subscribed = self
+            .cohort_subscribed
+            .checked_sub(user_stake.cohort_amount)
+            .ok_or(VaultError::MathOverflow)?;
+        self.cohort_weight_offset = self
+            .cohort_weight_offset
+            .checked_sub(user_stake.cohort_offset)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn update_cohort_fill(&mut self, current_time: i64) {
+        if self.cohort_target == 0 || self.cohort_subscribed < self.cohort_target {
+            self.cohort_filled_at = 0;
+        } else if self.cohort_filled_at == 0 {
+            self.cohort_filled_at = current_time;
+        }
+    }
+
+    pub fn set_cohort_target(
+        &mut self,
+        cohort_target: u64,
+        cohort_deadline: i64,
+        full_subscription_bonus_bps: u16,
+        current_time: i64,
+    ) {
+        self.cohort_target = cohort_target;
+        self.cohort_deadline = cohort_deadline;
+        self.full_subscription_bonus_bps = full_subscription_bonus_bps;
+        self.cohort_filled_at = 0;
+        self.update_cohort_fill(current_time);
+    }
+
+    pub fn is_cohort_fully_subscribed(&self) -> bool {
+        self.cohort_filled_at != 0 && self.cohort_filled_at <= self.cohort_deadline
+    }
+
+    pub fn close_cohort(&mut self, reward_pool: u64, current_time: i64) -> Result<()> {
+        let total_weight = (self.cohort_subscribed as i128)
+            .checked_mul(current_time as i128)
+            .and_then(|weight| weight.checked_sub(self.cohort_weight_offset))
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.cohort_total_weight = total_weight.max(0) as u128;
+        self.cohort_reward_pool = reward_pool;
+        self.cohort_closed_at = current_time;
+
+        let liability = self.cohort_liability()?;
+        self.add_outstanding_payout(liability)
+    }
+
+    pub fn calculate_full_subscription_bonus(&self, cohort_amount: u64) -> Result<u64> {
+        if !self.is_cohort_fully_subscribed() {
+            return Ok(0);
+        }
+
+        let bonus = (cohort_amount as u128)
+            .checked_mul(self.full_subscription_bonus_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(bonus).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn cohort_liability(&self) -> Result<u64> {
+        let bonus = self.calculate_full_subscription_bonus(self.cohort_subscribed)?;
+
+        self.cohort_reward_pool
+            .checked_add(bonus)
+            .ok_or(VaultError::MathOverflow.into())
+    }
+
+    pub fn calculate_cohort_claim(&self, user_stake: &UserStake) -> Result<u64> {
+        let weight = user_stake.cohort_weight(self.cohort_closed_at);
+        let bonus = self.calculate_full_subscription_bonus(user_stake.cohort_amount)?;
+
+        self.calculate_cohort_share(weight)?
+            .checked_add(bonus)
+            .ok_or(VaultError::MathOverflow.into())
+    }
+
+    pub fn calculate_cohort_share(&self, weight: u128) -> Result<u64> {
+        if self.cohort_total_weight == 0 {
+            return Ok(0);
+        }
+
+        let share = (self.cohort_reward_pool as u128)
+            .checked_mul(weight)
+            .ok_or(VaultError::MathOverflow)?
+            / self.cohort_total_weight;
+
+        u64::try_from(share).map_err(|_| VaultError::MathOverflow.into())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserStake {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub stake_years: u8,
+    pub stake_time: i64,
+    pub unlock_time: i64,
+    pub is_withdrawn: bool,
+    pub bump: u8,
+    pub beneficiary: Option<Pubkey>,
+    pub dormancy_period: i64,
+    pub installment_reward: u64,
+    pub installment_count: u8,
+    pub installments_paid: u8,
+    pub installment_interval: i64,
+    pub installment_start: i64,
+    pub goal_deadline: i64,
+    pub succeeded: bool,
+    pub in_cohort: bool,
+    pub stake_slot: u64,
+    pub active_from: i64,
+    pub notify_before_seconds: i64,
+    pub warned: bool,
+    pub epoch_floor: u64,
+    pub reward_compounded: bool,
+    pub max_lock_bonus_bps: u16,
+    pub savings_lock_seconds: i64,
+    pub guardian: Option<Pubkey>,
+    pub pending_payout: u64,
+    pub payout_available_at: i64,
+    pub disputed: bool,
+    pub auto_withdraw: bool,
+    pub curve_bonus_bps: u16,
+    pub committed_reward: u64,
+    pub cohort_amount: u64,
+    pub cohort_offset: i128,
+    pub payout_destination: Pubkey,
+    pub pending_reward: u64,
+    pub streak_bonus_bps: u16,
+    pub veteran_bonus_bps: u16,
+    pub pending_loyalty: u64,
+    pub forfeit_appealed: bool,
+    pub appeal_resolved: bool,
+    pub unbond_period: i64,
+    pub unbond_bonus_bps: u16,
+    pub utilization_bonus_bps: u16,
+    pub compound_split_bps: u16,
+    pub dca_amount: u64,
+    pub dca_interval: i64,
+    pub dca_next_time: i64,
+    pub dca_deposits: u16,
+    pub dca_bonus_bps: u16,
+}
+
+impl UserStake {
+    pub fn create_stake(
+        &mut self,
+        user: Pubkey,
+        amount: u64,
+        stake_years: u8,
+        clock: &Clock,
+        settlement_delay_seconds: i64,
+        bump: u8,
+    ) -> Result<()> {
+        self.user = user;
+        self.amount = amount;
+        self.stake_years = stake_years;
+        self.stake_time = clock.unix_timestamp;
+        self.stake_slot = clock.slot;
+        self.active_from = clock
+            .unix_timestamp
+            .checked_add(settlement_delay_seconds)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let lock_duration = (stake_years as i64) * SECONDS_PER_YEAR;
+
+        self.unlock_time = self
+            .active_from
+            .checked_add(lock_duration)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.is_withdrawn = false;
+        self.bump = bump;
+        self.beneficiary = None;
+        self.dormancy_period = 0;
+        self.installment_reward = 0;
+        self.installment_count = 0;
+        self.installments_paid = 0;
+        self.installment_interval = 0;
+        self.installment_start = 0;
+        self.goal_deadline = 0;
+        self.succeeded = false;
+        self.in_cohort = false;
+        self.notify_before_seconds = 0;
+        self.warned = false;
+        self.epoch_floor = amount;
+        self.reward_compounded = false;
+        self.max_lock_bonus_bps = 0;
+        self.savings_lock_seconds = 0;
+        self.guardian = None;
+        self.pending_payout = 0;
+        self.payout_available_at = 0;
+        self.disputed = false;
+        self.auto_withdraw = false;
+        self.curve_bonus_bps = 0;
+        self.committed_reward = 0;
+        self.cohort_amount = 0;
+        self.cohort_offset = 0;
+        self.payout_destination = Pubkey::default();
+        self.pending_reward = 0;
+        self.streak_bonus_bps = 0;
+        self.veteran_bonus_bps = 0;
+        self.pending_loyalty = 0;
+        self.forfeit_appealed = false;
+        self.appeal_resolved = false;
+        self.unbond_period = 0;
+        self.unbond_bonus_bps = 0;
+        self.utilization_bonus_bps = 0;
+        self.compound_split_bps = BPS_DENOMINATOR as u16;
+        self.dca_amount = 0;
+        self.dca_interval = 0;
+        self.dca_next_time = 0;
+        self.dca_deposits = 0;
+        self.dca_bonus_bps = 0;
+
+        Ok(())
+    }
+
+    pub fn is_reusable(&self) -> bool {
+        self.user == Pubkey::default()
+            || (self.is_withdrawn
+                && self.installments_paid >= self.installment_count
+                && self.pending_payout == 0)
+    }
+
+    pub fn check_if_unlocked(&self, current_time: i64) -> Result<()> {
+        require!(!self.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(current_time >= self.unlock_time, VaultError::StillLocked);
+        Ok(())
+    }
+
+    pub fn check_if_active(&self, current_time: i64) -> Result<()> {
+        require!(current_time >= self.active_from, VaultError::NotYetActive);
+        Ok(())
+    }
+
+    pub fn check_adjustment(
+        &self,
+        vault: &Vault,
+        amount: u64,
+        unlock_time: i64,
+        stake_years: u8,
+        vault_balance: u64,
+    ) -> Result<()> {
+        require!(!self.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(amount > 0, VaultError::InvalidAmount);
+        require!(
+            amount <= vault_balance,
+            VaultError::AdjustmentExceedsVaultBalance
+        );
+        require!(
+            unlock_time >= self.stake_time,
+            VaultError::InvalidUnlockTime
+        );
+        require!(
+            vault.is_valid_stake_period(stake_years),
+            VaultError::InvalidStakePeriod
+        );
+        Ok(())
+    }
+
+    pub fn check_collateral(&self, user: Pubkey, min_amount: u64, min_unlock: i64) -> Result<()> {
+        require_keys_eq!(self.user, user, VaultError::UnauthorizedUser);
+        require!(!self.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            self.amount >= min_amount,
+            VaultError::InsufficientCollateral
+        );
+        require!(
+            self.unlock_time >= min_unlock,
+            VaultError::CollateralUnlocksTooSoon
+        );
+        Ok(())
+    }
+
+    pub fn check_not_same_slot(&self, current_slot: u64) -> Result<()> {
+        require!(
+            current_slot != self.stake_slot,
+            VaultError::SameSlotWithdrawal
+        );
+        Ok(())
+    }
+
+    pub fn check_if_dormant(&self, current_time: i64) -> Result<()> {
+        self.check_if_unlocked(current_time)?;
+
+        let dormant_after = self
+            .unlock_time
+            .checked_add(self.dormancy_period)
+            .ok_or(VaultError::MathOverflow)?;
+
+        require!(current_time >= dormant_after, VaultError::StakeNotDormant);
+        Ok(())
+    }
+
+    pub fn check_if_commitment_settled(&self, current_time: i64) -> Result<()> {
+        require!(!self.forfeit_appealed, VaultError::AppealPending);
+
+        if self.goal_deadline != 0 && !self.succeeded {
+            require!(
+                current_time > self.goal_deadline,
+                VaultError::CommitmentPending
+            );
+        }
+        Ok(())
+    }
+
+    pub fn has_failed_commitment(&self) -> bool {
+        self.goal_deadline != 0 && !self.succeeded
+    }
+
+    pub fn has_lapsed_commitment(&self, current_time: i64) -> bool {
+        self.has_failed_commitment() && current_time > self.goal_deadline
+    }
+
+    pub fn earns_bonus(&self) -> bool {
+        self.stake_years > 0 && !self.has_failed_commitment()
+    }
+
+    pub fn calculate_reward_scaled(
+        &self,
+        bonus_bps: u64,
+        vault: &Vault,
+        current_time: i64,
+    ) -> Result<u128> {
+        if self.reward_compounded {
+            return Ok(0);
+        }
+
+        let reward_bps =
+            (self.get_multiplier() as u128 - 1) * BPS_DENOMINATOR as u128 + bonus_bps as u128;
+
+        let mut reward = (self.amount as u128)
+            .checked_mul(reward_bps)
+            .ok_or(VaultError::MathOverflow)?;
+
+        reward = self.apply_reward_ramp(reward, vault.reward_ramp_seconds, current_time)?;
+        reward = self.apply_reward_decay(reward, vault, current_time)?;
+
+        if vault.max_reward_per_stake > 0 {
+            reward = reward.min(vault.max_reward_per_stake as u128 * BPS_DENOMINATOR as u128);
+        }
+        Ok(reward)
+    }
+
+    pub fn calculate_total_return(
+        &self,
+        bonus_bps: u64,
+        vault: &Vault,
+        current_time: i64,
+    ) -> Result<u64> {
+        let reward =
+            self.calculate_reward_scaled(bonus_bps, vault, current_time)? / BPS_DENOMINATOR as u128;
+        let reward = u64::try_from(reward).map_err(|_| VaultError::MathOverflow)?;
+
+        self.amount
+            .checked_add(reward)
+            .ok_or(VaultError::MathOverflow.into())
+    }
+
+    pub fn apply_reward_ramp(
+        &self,
+        reward: u128,
+        reward_ramp_seconds: i64,
+        current_time: i64,
+    ) -> Result<u128> {
+        let age = current_time.saturating_sub(self.active_from).max(0);
+
+        if reward_ramp_seconds <= 0 || age >= reward_ramp_seconds {
+            return Ok(reward);
+        }
+
+        let ramped = reward
+            .checked_mul(age as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / reward_ramp_seconds as u128;
+        Ok(ramped)
+    }
+
+    pub fn apply_reward_decay(
+        &self,
+        reward: u128,
+        vault: &Vault,
+        current_time: i64,
+    ) -> Result<u128> {
+        if vault.reward_decay_seconds <= 0 {
+            return Ok(reward);
+        }
+
+        let decay_start = self
+            .unlock_time
+            .checked_add(vault.claim_window_seconds)
+            .ok_or(VaultError::MathOverflow)?;
+        let late_by = current_time.saturating_sub(decay_start);
+
+        if late_by <= 0 {
+            return Ok(reward);
+        }
+        if late_by >= vault.reward_decay_seconds {
+            return Ok(0);
+        }
+
+        let remaining = reward
+            .checked_mul((vault.reward_decay_seconds - late_by) as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / vault.reward_decay_seconds as u128;
+        Ok(remaining)
+    }
+
+    pub fn calculate_withdrawal_bonus_bps(&self, vault: &Vault, current_time: i64) -> u64 {
+        if !self.earns_bonus() {
+            return 0;
+        }
+        self.calculate_bonus_bps(vault, current_time)
+    }
+
+    fn calculate_bonus_bps(&self, vault: &Vault, current_time: i64) -> u64 {
+        let mut bonus_bps = (self.streak_bonus_bps as u64)
+            .saturating_add(self.veteran_bonus_bps as u64)
+            .saturating_add(self.max_lock_bonus_bps as u64)
+            .saturating_add(self.curve_bonus_bps as u64)
+            .saturating_add(self.unbond_bonus_bps as u64)
+            .saturating_add(self.utilization_bonus_bps as u64)
+            .saturating_add(self.dca_bonus_bps as u64);
+
+        if self.qualifies_for_congestion_bonus(vault, current_time) {
+            bonus_bps = bonus_bps.saturating_add(vault.congestion_bonus_bps as u64);
+        }
+        if self.qualifies_for_epoch_bonus(vault, current_time) {
+            bonus_bps = bonus_bps.saturating_add(vault.epoch_bonus_bps as u64);
+        }
+        bonus_bps
+    }
+
+    pub fn calculate_bonus(&self, bonus_bps: u64) -> Result<u64> {
+        let bonus = (self.amount as u128)
+            .checked_mul(bonus_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(bonus).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn calculate_committed_reward(&self, vault: &Vault) -> Result<u64> {
+        let base_reward = self
+            .amount
+            .checked_mul(self.get_multiplier() as u64 - 1)
+            .ok_or(VaultError::MathOverflow)?;
+        // Congestion and epoch bonuses only count when the window is already
+        // set at stake time; one started or changed later is not covered here.
+        let bonus = if self.stake_years > 0 {
+            self.calculate_bonus(self.calculate_bonus_bps(vault, self.unlock_time))?
+        } else {
+            0
+        };
+
+        let mut reward = base_reward
+            .checked_add(bonus)
+            .ok_or(VaultError::MathOverflow)?;
+
+        if vault.max_reward_per_stake > 0 {
+            reward = reward.min(vault.max_reward_per_stake);
+        }
+        Ok(reward)
+    }
+
+    pub fn set_committed_reward(&mut self, committed_reward: u64) {
+        self.committed_reward = committed_reward;
+    }
+
+    pub fn calculate_loyalty_reward(
+        &self,
+        stake_decimals: u8,
+        loyalty_decimals: u8,
+    ) -> Result<u64> {
+        let amount = self
+            .amount
+            .checked_mul(self.stake_years as u64)
+            .ok_or(VaultError::MathOverflow)?;
+
+        if loyalty_decimals >= stake_decimals {
+            10u64
+                .checked_pow((loyalty_decimals - stake_decimals) as u32)
+                .and_then(|scale| amount.checked_mul(scale))
+                .ok_or(VaultError::MathOverflow.into())
+        } else {
+            10u64
+                .checked_pow((stake_decimals - loyalty_decimals) as u32)
+                .and_then(|scale| amount.checked_div(scale))
+                .ok_or(VaultError::MathOverflow.into())
+        }
+    }
+
+    pub fn get_multiplier(&self) -> u8 {
+        match self.stake_years {
+            0 => 1,
+            1 => 1,
+            2 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn mark_as_withdrawn(&mut self) {
+        self.is_withdrawn = true;
+    }
+
+    pub fn adjust(&mut self, amount: u64, unlock_time: i64, stake_years: u8) {
+        self.amount = amount;
+        self.unlock_time = unlock_time;
+        self.stake_years = stake_years;
+    }
+
+    pub fn compound(&mut self, reward: u64) -> Result<()> {
+        self.amount = self
+            .amount
+            .checked_add(reward)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn set_compound_split(&mut self, compound_split_bps: u16) {
+        self.compound_split_bps = compound_split_bps;
+    }
+
+    pub fn calculate_compounded(&self, reward: u64) -> Result<u64> {
+        let compounded = (reward as u128)
+            .checked_mul(self.compound_split_bps as u128)
+            .ok_or(VaultError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(compounded).map_err(|_| VaultError::MathOverflow.into())
+    }
+
+    pub fn set_dca_schedule(
+        &mut self,
+        dca_amount: u64,
+        dca_interval: i64,
+        current_time: i64,
+    ) -> Result<()> {
+        self.dca_amount = dca_amount;
+        self.dca_interval = dca_interval;
+        self.dca_next_time = current_time
+            .checked_add(dca_interval)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    // A deposit made more than one interval after it fell due breaks the run
+    // and starts the count again.
+    pub fn record_dca_deposit(&mut self, current_time: i64) -> Result<()> {
+        let missed_after = self
+            .dca_next_time
+            .checked_add(self.dca_interval)
+            .ok_or(VaultError::MathOverflow)?;
+
+        self.dca_deposits = if current_time > missed_after {
+            1
+        } else {
+            self.dca_deposits.saturating_add(1)
+        };
+        self.dca_next_time = current_time
+            .checked_add(self.dca_interval)
+            .ok_or(VaultError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn set_dca_bonus(&mut self, dca_bonus_bps: u16) {
+        self.dca_bonus_bps = dca_bonus_bps;
+    }
+
+    pub fn mark_reward_compounded(&mut self) {
+        self.reward_compounded = true;
+    }
+
+    pub fn set_max_lock_bonus(&mut self, max_lock_bonus_bps: u16) {
+        self.max_lock_bonus_bps = max_lock_bonus_bps;
+    }
+
+    pub fn set_streak_bonus(&mut self, streak_bonus_bps: u16) {
+        self.streak_bonus_bps = streak_bonus_bps;
+    }
+
+    pub fn set_veteran_bonus(&mut self, veteran_bonus_bps: u16) {
+        self.veteran_bonus_bps = veteran_bonus_bps;
+    }
+
+    pub fn set_savings_lock(&mut self, savings_lock_seconds: i64) {
+        self.savings_lock_seconds = savings_lock_seconds;
+    }
+
+    pub fn set_guardian(&mut self, guardian: Option<Pubkey>) {
+        self.guardian = guardian;
+    }
+
+    pub fn set_auto_withdraw(&mut self, auto_withdraw: bool) {
+        self.auto_withdraw = auto_withdraw;
+    }
+
+    pub fn check_reward_unrestricted(&self, vault: &Vault) -> Result<()> {
+        require!(
+            self.savings_lock_seconds == 0
+                && vault.installment_count == 0
+                && vault.dispute_window_seconds == 0
+                && self.unbond_period == 0,
+            VaultError::RewardPayoutRestricted
+        );
+        Ok(())
+    }
+
+    pub fn check_auto_withdraw(&self) -> Result<()> {
+        require!(self.auto_withdraw, VaultError::AutoWithdrawNotEnabled);
+        Ok(())
+    }
+
+    pub fn set_curve_bonus(&mut self, curve_bonus_bps: u16) {
+        self.curve_bonus_bps = curve_bonus_bps;
+    }
+
+    pub fn hold_payout(
+        &mut self,
+        amount: u64,
+        reward: u64,
+        destination: Pubkey,
+        available_at: i64,
+    ) {
+        self.pending_payout = amount;
+        self.pending_reward = reward;
+        self.payout_destination = destination;
+        self.payout_available_at = available_at;
+        self.disputed = false;
+    }
+
+    pub fn set_disputed(&mut self, disputed: bool) {
+        self.disputed = disputed;
+    }
+
+    pub fn reject_payout(&mut self) -> Result<(u64, u64)> {
+        require!(self.disputed, VaultError::PayoutNotDisputed);
+
+        let reward = self.pending_reward;
+        let principal = self
+            .pending_payout
+            .checked_sub(reward)
+            .ok_or(VaultError::MathOverflow)?;
+        self.pending_payout = 0;
+        self.pending_reward = 0;
+        self.pending_loyalty = 0;
+        self.disputed = false;
+        Ok((principal, reward))
+    }
+
+    pub fn hold_loyalty(&mut self, loyalty_amount: u64) {
+        self.pending_loyalty = loyalty_amount;
+    }
+
+    pub fn take_pending_loyalty(&mut self) -> u64 {
+        std::mem::take(&mut self.pending_loyalty)
+    }
+
+    pub fn release_payout(&mut self, current_time: i64) -> Result<(u64, u64)> {
+        require!(self.pending_payout > 0, VaultError::NoPendingPayout);
+        require!(!self.disputed, VaultError::PayoutDisputed);
+        require!(
+            current_time >= self.payout_available_at,
+            VaultError::DisputeWindowOpen
+        );
+
+        let amount = self.pending_payout;
+        let reward = self.pending_reward;
+        self.pending_payout = 0;
+        self.pending_reward = 0;
+        Ok((amount, reward))
+    }
+
+    pub fn check_guardian(&self, signer: Option<Pubkey>) -> Result<()> {
+        if let Some(guardian) = self.guardian {
+            require!(
+                signer == Some(guardian),
+                VaultError::GuardianSignatureRequired
+            );
+        }
+        Ok(())
+    }
+
+    pub fn update_epoch_floor(&mut self, vault: &Vault, current_time: i64) {
+        if current_time < vault.epoch_start {
+            self.epoch_floor = self.amount;
+        } else if current_time < vault.epoch_end {
+            self.epoch_floor = self.epoch_floor.min(self.amount);
+        }
+    }
+
+    pub fn qualifies_for_epoch_bonus(&self, vault: &Vault, current_time: i64) -> bool {
+        vault.epoch_bonus_bps > 0
+            && self.stake_time <= vault.epoch_start
+            && current_time >= vault.epoch_end
+            && self.epoch_floor >= vault.epoch_min_stake
+    }
+
+    pub fn qualifies_for_congestion_bonus(&self, vault: &Vault, current_time: i64) -> bool {
+        vault.congestion_bonus_bps > 0
+            && self.stake_time <= vault.congestion_start
+            && current_time >= vault.congestion_end
+    }
+
+    pub fn set_beneficiary(
+        &mut self,
+        beneficiary: Option<Pubkey>,
+        dormancy_period: i64,
+    ) -> Result<()> {
+        if beneficiary.is_some() {
+            require!(
+                dormancy_period >= MIN_DORMANCY_PERIOD,
+                VaultError::InvalidDormancyPeriod
+            );
+        }
+
+        self.beneficiary = beneficiary;
+        self.dormancy_period = dormancy_period;
+        Ok(())
+    }
+
+    pub fn schedule_installments(
+        &mut self,
+        reward: u64,
+        installment_count: u8,
+        installment_interval: i64,
+        start_time: i64,
+    ) {
+        self.installment_reward = reward;
+        self.installment_count = installment_count;
+        self.installments_paid = 0;
+        self.installment_interval = installment_interval;
+        self.installment_start = start_time;
+    }
+
+    pub fn claimable_installments(&self, current_time: i64) -> Result<(u8, u64)> {
+        if self.installments_paid >= self.installment_count {
+            return Ok((0, 0));
+        }
+
+        let elapsed = current_time.saturating_sub(self.installment_start);
+        let vested = (elapsed / self.installment_interval).min(self.installment_count as i64) as u8;
+        let installments_due = vested.saturating_sub(self.installments_paid);
+
+        let per_installment = self.installment_reward / self.installment_count as u64;
+        let mut amount = per_installment
+            .checked_mul(installments_due as u64)
+            .ok_or(VaultError::MathOverflow)?;
+
+        if installments_due > 0 && vested == self.installment_count {
+            let remainder = self.installment_reward % self.installment_count as u64;
+            amount = amount
+                .checked_add(remainder)
+                .ok_or(VaultError::MathOverflow)?;
+        }
+
+        Ok((installments_due, amount))
+    }
+
+    pub fn record_installments_paid(&mut self, installments: u8) {
+        self.installments_paid += installments;
+    }
+
+    pub fn set_commitment_goal(&mut self, goal_deadline: i64) {
+        self.goal_deadline = goal_deadline;
+        self.succeeded = false;
+    }
+
+    pub fn mark_goal_succeeded(&mut self) {
+        self.succeeded = true;
+    }
+
+    pub fn set_unbond_period(&mut self, unbond_period: i64, unbond_bonus_bps: u16) {
+        self.unbond_period = unbond_period;
+        self.unbond_bonus_bps = unbond_bonus_bps;
+    }
+
+    pub fn set_utilization_bonus(&mut self, utilization_bonus_bps: u16) {
+        self.utilization_bonus_bps = utilization_bonus_bps;
+    }
+
+    pub fn appeal_forfeit(&mut self) {
+        self.forfeit_appealed = true;
+    }
+
+    pub fn resolve_appeal(&mut self) {
+        self.forfeit_appealed = false;
+        self.appeal_resolved = true;
+    }
+
+    pub fn cohort_weight(&self, until: i64) -> u128 {
+        ((self.cohort_amount as i128) * (until as i128) - self.cohort_offset).max(0) as u128
+    }
+
+    pub fn join_cohort(&mut self, current_time: i64) {
+        self.in_cohort = true;
+        self.cohort_amount = self.amount;
+        self.cohort_offset = (self.amount as i128) * (current_time as i128);
+    }
+
+    pub fn resize_cohort(&mut self, current_time: i64) {
+        let delta = (self.amount as i128) - (self.cohort_amount as i128);
+        self.cohort_offset += delta * (current_time as i128);
+        self.cohort_amount = self.amount;
+    }
+
+    pub fn leave_cohort(&mut self) {
+        self.in_cohort = false;
+        self.cohort_amount = 0;
+        self.cohort_offset = 0;
+    }
+
+    pub fn set_maturity_notification(&mut self, notify_before_seconds: i64) {
+        self.notify_before_seconds = notify_before_seconds;
+        self.warned = false;
+    }
+
+    pub fn check_if_in_notification_window(&self, current_time: i64) -> Result<()> {
+        require!(!self.is_withdrawn, VaultError::AlreadyWithdrawn);
+        require!(
+            self.notify_before_seconds > 0,
+            VaultError::NoMaturityNotification
+        );
+        require!(!self.warned, VaultError::MaturityAlreadyWarned);
+
+        let window_start = self
+            .unlock_time
+            .checked_sub(self.notify_before_seconds)
+            .ok_or(VaultError::MathOverflow)?;
+
+        require!(
+            current_time >= window_start,
+            VaultError::NotInNotificationWindow
+        );
+        Ok(())
+    }
+
+    pub fn mark_as_warned(&mut self) {
+        self.warned = true;
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserStats {
+    pub user: Pubkey,
+    pub current_streak: u16,
+    pub bump: u8,
+}
+
+impl UserStats {
+    pub fn record_completed_stake(&mut self, user: Pubkey, bump: u8) {
+        self.user = user;
+        self.bump = bump;
+        self.current_streak = self.current_streak.saturating_add(1);
+    }
+
+    pub fn break_streak(&mut self, user: Pubkey, bump: u8) {
+        self.user = user;
+        self.bump = bump;
+        self.current_streak = 0;
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RewardLedger {
+    pub vault: Pubkey,
+    pub bump: u8,
+    pub next_index: u16,
+    pub total_entries: u64,
+    #[max_len(REWARD_LEDGER_CAPACITY)]
+    pub entries: Vec<RewardLedgerEntry>,
+}
+
+impl RewardLedger {
+    pub fn initialize(&mut self, vault: Pubkey, bump: u8) {
+        self.vault = vault;
+        self.bump = bump;
+        self.next_index = 0;
+        self.total_entries = 0;
+        self.entries = Vec::new();
+    }
+
+    pub fn record(&mut self, entry: RewardLedgerEntry) {
+        let index = self.next_index as usize;
+
+        if self.entries.len() < REWARD_LEDGER_CAPACITY {
+            self.entries.push(entry);
+        } else {
+            self.entries[index] = entry;
+        }
+
+        self.next_index = ((index + 1) % REWARD_LEDGER_CAPACITY) as u16;
+        self.total_entries = self.total_entries.saturating_add(1);
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct RewardLedgerEntry {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct RateChange {
+    pub rate: u8,
+    pub timestamp: i64,
+    pub old_bps: u16,
+    pub new_bps: u16,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserProfile {
+    pub user: Pubkey,
+    pub first_stake_ever: i64,
+    pub bump: u8,
+}
+
+impl UserProfile {
+    pub fn record_stake(&mut self, user: Pubkey, bump: u8, stake_time: i64) {
+        self.user = user;
+        self.bump = bump;
+
+        if self.first_stake_ever == 0 || stake_time < self.first_stake_ever {
+            self.first_stake_ever = stake_time;
+        }
+    }
+
+    pub fn tenure(&self, current_time: i64) -> i64 {
+        current_time.saturating_sub(self.first_stake_ever).max(0)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SavingsVault {
+    pub vault: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub release_time: i64,
+    pub bump: u8,
+}
+
+impl SavingsVault {
+    pub fn initialize_if_needed(&mut self, vault: Pubkey, user: Pubkey, bump: u8) {
+        if self.user == Pubkey::default() {
+            self.vault = vault;
+            self.user = user;
+            self.amount = 0;
+            self.release_time = 0;
+            self.bump = bump;
+        }
+    }
+
+    pub fn deposit(&mut self, amount: u64, current_time: i64, lock_seconds: i64) -> Result<()> {
+        self.amount = self
+            .amount
+            .checked_add(amount)
+            .ok_or(VaultError::MathOverflow)?;
+
+        let release_time = current_time
+            .checked_add(lock_seconds)
+            .ok_or(VaultError::MathOverflow)?;
+        self.release_time = self.release_time.max(release_time);
+        Ok(())
+    }
+
+    pub fn release(&mut self, current_time: i64) -> Result<u64> {
+        require!(self.amount > 0, VaultError::NoSavingsToRelease);
+        require!(
+            current_time >= self.release_time,
+            VaultError::SavingsStillLocked
+        );
+
+        let amount = self.amount;
+        self.amount = 0;
+        Ok(amount)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MerkleCampaign {
+    pub vault: Pubkey,
+    pub merkle_root: [u8; 32],
+    pub remaining_allocation: u64,
+    pub bump: u8,
+    pub claimed: [u8; MERKLE_BITMAP_BYTES],
+}
+
+impl MerkleCampaign {
+    pub fn start(&mut self, vault: Pubkey, merkle_root: [u8; 32], total_allocation: u64, bump: u8) {
+        self.vault = vault;
+        self.merkle_root = merkle_root;
+        self.remaining_allocation = total_allocation;
+        self.bump = bump;
+        self.claimed = [0; MERKLE_BITMAP_BYTES];
+    }
+
+    pub fn verify_proof(&self, proof: &[[u8; 32]], leaf: [u8; 32]) -> bool {
+        let mut node = leaf;
+
+        for sibling in proof {
+            node = if node <= *sibling {
+                keccak::hashv(&[&node, sibling]).0
+            } else {
+                keccak::hashv(&[sibling, &node]).0
+            };
+        }
+
+        node == self.merkle_root
+    }
+
+    pub fn claim(&mut self, index: u32, amount: u64) -> Result<()> {
+        let byte = (index / 8) as usize;
+        let bit = 1u8 << (index % 8);
+
+        require!(
+            byte < MERKLE_BITMAP_BYTES,
+            VaultError::MerkleIndexOutOfRange
+        );
+        require!(
+            self.claimed[byte] & bit == 0,
+            VaultError::MerkleBonusAlreadyClaimed
+        );
+
+        self.remaining_allocation = self
+            .remaining_allocation
+            .checked_sub(amount)
+            .ok_or(VaultError::CampaignAllocationExceeded)?;
+        self.claimed[byte] |= bit;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ReserveStake {
+    pub vault: Pubkey,
+    pub user: Pubkey,
+    pub shares: u64,
+    pub epoch: u32,
+    pub bump: u8,
+}
+
+impl ReserveStake {
+    pub fn initialize_if_needed(&mut self, vault: Pubkey, user: Pubkey, bump: u8) {
+        if self.user == Pubkey::default() {
+            self.vault = vault;
+            self.user = user;
+            self.shares = 0;
+            self.epoch = 0;
+            self.bump = bump;
+        }
+    }
+
+    pub fn active_shares(&self, epoch: u32) -> u64 {
+        if self.epoch == epoch {
+            self.shares
+        } else {
+            0
+        }
+    }
+
+    pub fn deposit(&mut self, shares: u64, epoch: u32) -> Result<()> {
+        self.shares = self
+            .active_shares(epoch)
+            .checked_add(shares)
+            .ok_or(VaultError::MathOverflow)?;
+        self.epoch = epoch;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self) {
+        self.shares = 0;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NextUnlock {
+    pub user_stake: Pubkey,
+    pub unlock_time: i64,
+    pub amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReserveSnapshot {
+    pub slot: u64,
+    pub timestamp: i64,
+    pub principal_held: u64,
+    pub accrued_management_fee: u64,
+    pub reserve_balance: u64,
+    pub reward_reserve: u64,
+    pub collateralization_bps: u64,
+    pub committed_rewards: u64,
+    pub outstanding_payouts: u64,
+    pub insurance_balance: u64,
+}
+
+#[event]
 pub struct StakeCreatedEvent {
-    #[index]
     pub user: Pubkey,
     pub amount: u64,
-    pub stake_years: u8,
-    pub unlock_time: i64,
+    pub stake_years: u8,
+    pub unlock_time: i64,
+}
+
+#[event]
+pub struct StakeWithdrawnEvent {
+    pub user: Pubkey,
+    pub original_amount: u64,
+    pub total_return: u64,
+    pub multiplier: u8,
+}
+
+#[event]
+pub struct StakeAdjustedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub old_amount: u64,
+    pub new_amount: u64,
+    pub old_unlock_time: i64,
+    pub new_unlock_time: i64,
+    pub old_stake_years: u8,
+    pub new_stake_years: u8,
+}
+
+#[event]
+pub struct BeneficiaryClaimedEvent {
+    pub user: Pubkey,
+    pub beneficiary: Pubkey,
+    pub total_return: u64,
+}
+
+#[event]
+pub struct LoyaltyMintedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct InstallmentClaimedEvent {
+    pub user: Pubkey,
+    pub installments_paid: u8,
+    pub installment_count: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct StreakUpdatedEvent {
+    pub user: Pubkey,
+    pub current_streak: u16,
+    pub streak_bonus: u64,
+}
+
+#[event]
+pub struct CommitmentAttestedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub goal_deadline: i64,
+}
+
+#[event]
+pub struct CommitmentForfeitedEvent {
+    pub user: Pubkey,
+    pub forfeited: u64,
+}
+
+#[event]
+pub struct ManagementFeeClaimedEvent {
+    pub admin: Pubkey,
+    pub amount: u64,
+    pub reserve_stake_share: u64,
+    pub to_reserve: u64,
+}
+
+#[event]
+pub struct CohortClosedEvent {
+    pub total_weight: u128,
+    pub reward_pool: u64,
+    pub closed_at: i64,
+}
+
+#[event]
+pub struct CohortShareClaimedEvent {
+    pub user: Pubkey,
+    pub weight: u128,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MaturityApproachingEvent {
+    pub user: Pubkey,
+    pub unlock_time: i64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RewardCompoundedEvent {
+    pub user: Pubkey,
+    pub source_stake: Pubkey,
+    pub target_stake: Pubkey,
+    pub amount: u64,
+    pub paid_out: u64,
+}
+
+#[event]
+pub struct RewardsFundedEvent {
+    pub funder: Pubkey,
+    pub vault: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SavingsDepositedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub release_time: i64,
+}
+
+#[event]
+pub struct SavingsReleasedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MerkleBonusClaimedEvent {
+    pub user: Pubkey,
+    pub index: u32,
+    pub amount: u64,
+}
+
+#[event]
+pub struct WithdrawalHeldEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub available_at: i64,
+}
+
+#[event]
+pub struct WithdrawalFinalizedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PayoutDisputedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DisputeResolvedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub released: bool,
+    pub refunded_principal: u64,
+    pub forfeited_reward: u64,
+}
+
+#[event]
+pub struct RoundingEvent {
+    pub user: Pubkey,
+    pub exact_reward_scaled: u128,
+    pub paid_reward: u64,
+    pub remainder_scaled: u64,
+    pub scale: u64,
+}
+
+#[event]
+pub struct ForfeitedRewardReleasedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub released: u64,
+}
+
+#[event]
+pub struct ReserveStakedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct ReserveUnstakedEvent {
+    pub user: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct ForfeitAppealedEvent {
+    pub user: Pubkey,
+    pub goal_deadline: i64,
+}
+
+#[event]
+pub struct AppealResolvedEvent {
+    pub user: Pubkey,
+    pub admin: Pubkey,
+    pub upheld: bool,
+}
+
+#[event]
+pub struct DcaDepositEvent {
+    pub user: Pubkey,
+    pub keeper: Pubkey,
+    pub amount: u64,
+    pub dca_deposits: u16,
+}
+
+#[error_code]
+pub enum VaultError {
+    #[msg("Amount must be greater than 0")]
+    InvalidAmount,
+
+    #[msg("Stake period must be 1 or 2 years")]
+    InvalidStakePeriod,
+
+    #[msg("Tokens are still locked")]
+    StillLocked,
+
+    #[msg("Stake already withdrawn")]
+    AlreadyWithdrawn,
+
+    #[msg("Unauthorized user")]
+    UnauthorizedUser,
+
+    #[msg("Math overflow error")]
+    MathOverflow,
+
+    #[msg("Unauthorized admin")]
+    UnauthorizedAdmin,
+
+    #[msg("Adjusted amount exceeds vault balance")]
+    AdjustmentExceedsVaultBalance,
+
+    #[msg("Unlock time cannot be before stake time")]
+    InvalidUnlockTime,
+
+    #[msg("Dormancy period is shorter than the minimum")]
+    InvalidDormancyPeriod,
+
+    #[msg("Signer is not the stake's beneficiary")]
+    UnauthorizedBeneficiary,
+
+    #[msg("Stake has not been dormant long enough")]
+    StakeNotDormant,
+
+    #[msg("Loyalty mint does not match the vault's loyalty mint")]
+    InvalidLoyaltyMint,
+
+    #[msg("Loyalty mint and token account are required for this vault")]
+    LoyaltyAccountsRequired,
+
+    #[msg("User and vault token accounts have different mints")]
+    MintMismatch,
+
+    #[msg("Installment interval must be greater than 0")]
+    InvalidInstallmentSchedule,
+
+    #[msg("No installment is due yet")]
+    InstallmentNotDue,
+
+    #[msg("User already has an active stake in this vault")]
+    StakeAlreadyActive,
+
+    #[msg("Locks longer than the vault's long lock threshold must be acknowledged")]
+    LongLockNotAcknowledged,
+
+    #[msg("Basis points cannot exceed 10000")]
+    InvalidBasisPoints,
+
+    #[msg("Stake already has a commitment goal")]
+    CommitmentGoalAlreadySet,
+
+    #[msg("Goal deadline must be in the future")]
+    InvalidGoalDeadline,
+
+    #[msg("Stake has no commitment goal")]
+    NoCommitmentGoal,
+
+    #[msg("Commitment goal already attested")]
+    CommitmentAlreadyAttested,
+
+    #[msg("Goal deadline has passed")]
+    GoalDeadlinePassed,
+
+    #[msg("Commitment goal is still pending")]
+    CommitmentPending,
+
+    #[msg("No management fee has accrued")]
+    NoManagementFeeAccrued,
+
+    #[msg("Cohort is already closed")]
+    CohortAlreadyClosed,
+
+    #[msg("Cohort has not been closed yet")]
+    CohortNotClosed,
+
+    #[msg("Stake is not part of the cohort or already claimed its share")]
+    NotInCohort,
+
+    #[msg("Unknown event flag bits set")]
+    InvalidEventFlags,
+
+    #[msg("Cannot withdraw in the same slot the stake was created")]
+    SameSlotWithdrawal,
+
+    #[msg("Settlement delay cannot be negative")]
+    InvalidSettlementDelay,
+
+    #[msg("Stake is still within its settlement delay")]
+    NotYetActive,
+
+    #[msg("Notification threshold cannot be negative")]
+    InvalidNotificationThreshold,
+
+    #[msg("Stake has no maturity notification set")]
+    NoMaturityNotification,
+
+    #[msg("Maturity warning was already emitted for this stake")]
+    MaturityAlreadyWarned,
+
+    #[msg("Stake is not yet within its notification window")]
+    NotInNotificationWindow,
+
+    #[msg("Reward ramp must be between 0 and the one-year lock period")]
+    InvalidRewardRamp,
+
+    #[msg("Reward ledger account is required when the vault has one")]
+    RewardLedgerRequired,
+
+    #[msg("Epoch end must be after epoch start")]
+    InvalidEpoch,
+
+    #[msg("Stake has no reward left to compound")]
+    NoRewardToCompound,
+
+    #[msg("Target stake must still be locked")]
+    TargetStakeUnlocked,
+
+    #[msg("Cannot compound a stake into itself")]
+    SameStakeCompound,
+
+    #[msg("Staked amount is below the required collateral")]
+    InsufficientCollateral,
+
+    #[msg("Stake unlocks before the required time")]
+    CollateralUnlocksTooSoon,
+
+    #[msg("Claim window and decay period cannot be negative")]
+    InvalidRewardDecay,
+
+    #[msg("Allocation weights must cover every vault and sum to 10000 bps")]
+    InvalidAllocationWeights,
+
+    #[msg("Token account is not owned by the target vault")]
+    InvalidDistributionTarget,
+
+    #[msg("Savings lock cannot be negative")]
+    InvalidSavingsLock,
+
+    #[msg("Savings account is required when the stake routes rewards to savings")]
+    SavingsAccountRequired,
+
+    #[msg("Savings account has nothing to release")]
+    NoSavingsToRelease,
+
+    #[msg("Savings are still locked")]
+    SavingsStillLocked,
+
+    #[msg("Stake guardian must co-sign this instruction")]
+    GuardianSignatureRequired,
+
+    #[msg("Merkle proof does not match the campaign root")]
+    InvalidMerkleProof,
+
+    #[msg("Merkle bonus already claimed")]
+    MerkleBonusAlreadyClaimed,
+
+    #[msg("Merkle leaf index is out of range")]
+    MerkleIndexOutOfRange,
+
+    #[msg("Claim exceeds the campaign's remaining allocation")]
+    CampaignAllocationExceeded,
+
+    #[msg("Dispute window cannot be negative")]
+    InvalidDisputeWindow,
+
+    #[msg("Stake has no pending payout")]
+    NoPendingPayout,
+
+    #[msg("Dispute window has already closed")]
+    DisputeWindowClosed,
+
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+
+    #[msg("Pending payout is disputed")]
+    PayoutDisputed,
+
+    #[msg("Pending payout is not disputed")]
+    PayoutNotDisputed,
+
+    #[msg("One-year threshold must not exceed the two-year threshold")]
+    InvalidMinLockCurve,
+
+    #[msg("Lock period is too short for this stake amount")]
+    LockTooShortForAmount,
+
+    #[msg("Stake has not opted into keeper auto-withdrawal")]
+    AutoWithdrawNotEnabled,
+
+    #[msg("Clock tolerance cannot be negative")]
+    InvalidClockTolerance,
+
+    #[msg("Clock timestamp diverges too far from the slot-derived time")]
+    ClockUnreliable,
+
+    #[msg("Bonding curve span must be positive when a start bonus is set")]
+    InvalidBondingCurve,
+
+    #[msg("Reward reserve would fall below the required buffer over committed rewards")]
+    InsufficientReserveBuffer,
+
+    #[msg("Reserve buffer must be 0 or at least 10000 bps")]
+    InvalidReserveBuffer,
+
+    #[msg("Claim the closed cohort share before restaking")]
+    CohortShareUnclaimed,
+
+    #[msg("Vault holds no surplus above its liabilities to pay the management fee")]
+    NoFeeSurplus,
+
+    #[msg("Reward reserve does not cover the payout after committed rewards")]
+    InsufficientRewardReserve,
+
+    #[msg("Destination does not match the held payout's destination")]
+    InvalidPayoutDestination,
+
+    #[msg("Reward is subject to a savings lock, installment schedule or dispute window")]
+    RewardPayoutRestricted,
+
+    #[msg(
+        "Only classic SPL Token mints are supported; rebasing or interest-bearing mints are not"
+    )]
+    RebasingMintUnsupported,
+
+    #[msg("Stake has no committed reward to release")]
+    NoCommittedReward,
+
+    #[msg("No reserve stake to withdraw")]
+    NoReserveStake,
+
+    #[msg("Forfeit has already been appealed")]
+    AppealAlreadyFiled,
+
+    #[msg("Stake has no pending forfeit appeal")]
+    NoPendingAppeal,
+
+    #[msg("Forfeit appeal is pending")]
+    AppealPending,
+
+    #[msg("Unbonding period is negative, shorter than already chosen, or above the vault maximum")]
+    InvalidUnbondPeriod,
+
+    #[msg("Utilization curve needs a capacity, a kink below 100%, and a kink bonus no higher than the base bonus")]
+    InvalidUtilizationCurve,
+
+    #[msg("A recurring deposit needs a positive interval")]
+    InvalidDcaSchedule,
+
+    #[msg("Stake has no recurring deposit scheduled")]
+    NoDcaSchedule,
+
+    #[msg("Recurring deposit is not due yet")]
+    DcaDepositNotDue,
 }
 
-#[event]
-pub struct StakeWithdrawnEvent {
-    #[index]
-    pub user: Pubkey,
-    pub original_amount: u64,
-    pub total_return: u64,
-    pub multiplier: u8,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::ProgramResult;
+    use anchor_lang::solana_program::instruction::Instruction;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_lang::solana_program::{program_stubs, system_program};
+    use anchor_spl::token::spl_token;
+    use std::cell::RefCell;
+    use std::collections::BTreeSet;
+    use std::sync::Once;
+
+    const START: i64 = 1_700_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn zeroed<T: AnchorDeserialize>() -> T {
+        T::deserialize(&mut &[0u8; 2048][..]).unwrap()
+    }
+
+    fn clock_at(unix_timestamp: i64, slot: u64) -> Clock {
+        Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    fn new_vault() -> Vault {
+        let mut vault: Vault = zeroed();
+        vault.initialize(Pubkey::new_unique(), 255, START);
+        vault
+    }
+
+    fn token_account(mint: Pubkey, owner: Pubkey) -> TokenAccount {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        TokenAccount::try_deserialize(&mut &data[..]).unwrap()
+    }
+
+    thread_local! {
+        static CLOCK: RefCell<Clock> = RefCell::new(clock_at(START, 1));
+    }
+
+    fn set_clock(unix_timestamp: i64, slot: u64) {
+        CLOCK.with(|clock| *clock.borrow_mut() = clock_at(unix_timestamp, slot));
+    }
+
+    struct ProgramStubs;
+
+    impl program_stubs::SyscallStubs for ProgramStubs {
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { var_addr.cast::<Rent>().write_unaligned(Rent::default()) };
+            0
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = CLOCK.with(|clock| clock.borrow().clone());
+            unsafe { var_addr.cast::<Clock>().write_unaligned(clock) };
+            0
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            if instruction.program_id == system_program::ID {
+                return create_account(instruction, account_infos);
+            }
+            assert_eq!(instruction.program_id, spl_token::ID);
+
+            let signers: Vec<Pubkey> = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &crate::ID).unwrap())
+                .collect();
+            let infos: Vec<AccountInfo> = instruction
+                .accounts
+                .iter()
+                .map(|meta| {
+                    let mut info = account_infos
+                        .iter()
+                        .find(|info| *info.key == meta.pubkey)
+                        .unwrap()
+                        .clone();
+                    info.is_signer |= signers.contains(info.key);
+                    info
+                })
+                .collect();
+
+            spl_token::processor::Processor::process(&spl_token::ID, &infos, &instruction.data)
+        }
+    }
+
+    fn create_account(instruction: &Instruction, account_infos: &[AccountInfo]) -> ProgramResult {
+        let data = &instruction.data;
+        assert_eq!(data[..4], [0, 0, 0, 0]);
+        let lamports = u64::from_le_bytes(data[4..12].try_into().unwrap());
+        let owner = Pubkey::try_from(&data[20..52]).unwrap();
+        let find = |key: &Pubkey| account_infos.iter().find(|info| info.key == key).unwrap();
+        let from = find(&instruction.accounts[0].pubkey);
+        let to = find(&instruction.accounts[1].pubkey);
+
+        let remaining = from
+            .lamports()
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        **from.lamports.borrow_mut() = remaining;
+        **to.lamports.borrow_mut() = lamports;
+        to.assign(&owner);
+        Ok(())
+    }
+
+    fn account_info(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(ProgramStubs));
+        });
+
+        let lamports = Rent::default().minimum_balance(data.len());
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            false,
+            true,
+            Box::leak(Box::new(lamports)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    fn program_account_info<T: AccountSerialize + Space>(
+        key: Pubkey,
+        value: &T,
+    ) -> AccountInfo<'static> {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        data.resize(8 + T::INIT_SPACE, 0);
+        account_info(key, crate::ID, data)
+    }
+
+    fn token_account_info(key: Pubkey, mint: Pubkey, owner: Pubkey) -> AccountInfo<'static> {
+        funded_token_account_info(key, mint, owner, 0)
+    }
+
+    fn funded_token_account_info(
+        key: Pubkey,
+        mint: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+    ) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(key, spl_token::ID, data)
+    }
+
+    fn mint_info(key: Pubkey) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(key, spl_token::ID, data)
+    }
+
+    fn authority_mint_info(key: Pubkey, authority: Pubkey, decimals: u8) -> AccountInfo<'static> {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: Some(authority).into(),
+            decimals,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        account_info(key, spl_token::ID, data)
+    }
+
+    fn signer_info(key: Pubkey) -> AccountInfo<'static> {
+        let mut info = account_info(key, system_program::ID, Vec::new());
+        info.is_signer = true;
+        info
+    }
+
+    fn token_balance(info: &AccountInfo) -> u64 {
+        spl_token::state::Account::unpack(&info.data.borrow())
+            .unwrap()
+            .amount
+    }
+
+    fn load<T: AccountDeserialize>(info: &AccountInfo) -> T {
+        T::try_deserialize(&mut &info.data.borrow()[..]).unwrap()
+    }
+
+    fn none_info() -> AccountInfo<'static> {
+        executable_info(crate::ID)
+    }
+
+    /// Parses `$accounts` from `$infos`, runs the handler and writes the
+    /// accounts back, the way the runtime would for a single instruction.
+    macro_rules! run_instruction {
+        ($accounts:ident, $handler:ident, $infos:expr $(, $arg:expr)*) => {{
+            let mut remaining: &[AccountInfo<'static>] = Box::leak($infos.clone().into_boxed_slice());
+            let mut bumps = <$accounts as anchor_lang::Bumps>::Bumps::default();
+            $accounts::try_accounts(
+                &crate::ID,
+                &mut remaining,
+                &[],
+                &mut bumps,
+                &mut BTreeSet::new(),
+            )
+            .and_then(|mut accounts| {
+                advanced_vault::$handler(
+                    Context::new(&crate::ID, &mut accounts, remaining, bumps) $(, $arg)*
+                )?;
+                accounts.exit(&crate::ID)
+            })
+        }};
+    }
+
+    struct TestVault {
+        admin: Pubkey,
+        key: Pubkey,
+        mint: Pubkey,
+        vault: Vault,
+        token_account: AccountInfo<'static>,
+    }
+
+    impl TestVault {
+        fn new(mint: Pubkey, balance: u64) -> Self {
+            let admin = Pubkey::new_unique();
+            let (key, bump) = Pubkey::find_program_address(&[b"vault", admin.as_ref()], &crate::ID);
+            let mut vault: Vault = zeroed();
+            vault.initialize(admin, bump, START);
+
+            TestVault {
+                admin,
+                key,
+                mint,
+                vault,
+                token_account: funded_token_account_info(
+                    get_associated_token_address(&key, &mint),
+                    mint,
+                    key,
+                    balance,
+                ),
+            }
+        }
+
+        fn info(&self) -> AccountInfo<'static> {
+            program_account_info(self.key, &self.vault)
+        }
+
+        fn stats_info(&self, user: Pubkey, current_streak: u16) -> AccountInfo<'static> {
+            let (key, bump) = Pubkey::find_program_address(
+                &[b"user_stats", self.key.as_ref(), user.as_ref()],
+                &crate::ID,
+            );
+            let mut user_stats: UserStats = zeroed();
+            user_stats.user = user;
+            user_stats.bump = bump;
+            user_stats.current_streak = current_streak;
+            program_account_info(key, &user_stats)
+        }
+
+        fn stake_for(&self, user: Pubkey, amount: u64, stake_years: u8) -> (Pubkey, UserStake) {
+            let (key, bump) = Pubkey::find_program_address(
+                &[b"user_stake", self.key.as_ref(), user.as_ref()],
+                &crate::ID,
+            );
+            let mut user_stake: UserStake = zeroed();
+            user_stake
+                .create_stake(user, amount, stake_years, &clock_at(START, 1), 0, bump)
+                .unwrap();
+            (key, user_stake)
+        }
+    }
+
+    fn executable_info(key: Pubkey) -> AccountInfo<'static> {
+        let mut info = account_info(key, Pubkey::default(), Vec::new());
+        info.is_writable = false;
+        info.executable = true;
+        info
+    }
+
+    fn try_rescue_to(destination: fn(Pubkey, Pubkey) -> (Pubkey, Pubkey)) -> Result<()> {
+        let admin = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let seeds_for = |prefix: &[u8], vault: &Pubkey| {
+            Pubkey::find_program_address(&[prefix, vault.as_ref(), user.as_ref()], &crate::ID)
+        };
+
+        let (vault_key, vault_bump) =
+            Pubkey::find_program_address(&[b"vault", admin.as_ref()], &crate::ID);
+        let mut vault: Vault = zeroed();
+        vault.initialize(admin, vault_bump, START);
+
+        let (user_stake_key, user_stake_bump) = seeds_for(b"user_stake", &vault_key);
+        let mut user_stake = new_stake(1_000, 1);
+        user_stake.user = user;
+        user_stake.bump = user_stake_bump;
+
+        let (user_stats_key, _) = seeds_for(b"user_stats", &vault_key);
+        let (user_profile_key, _) =
+            Pubkey::find_program_address(&[b"user_profile", user.as_ref()], &crate::ID);
+        let (destination_mint, destination_owner) = destination(mint, user);
+
+        let mut user_info = account_info(user, system_program::ID, Vec::new());
+        user_info.is_signer = true;
+
+        let infos = vec![
+            program_account_info(vault_key, &vault),
+            program_account_info(user_stake_key, &user_stake),
+            user_info,
+            program_account_info(user_stats_key, &zeroed::<UserStats>()),
+            program_account_info(user_profile_key, &zeroed::<UserProfile>()),
+            token_account_info(Pubkey::new_unique(), destination_mint, destination_owner),
+            token_account_info(
+                get_associated_token_address(&vault_key, &mint),
+                mint,
+                vault_key,
+            ),
+            mint_info(mint),
+            executable_info(crate::ID),
+            executable_info(crate::ID),
+            executable_info(crate::ID),
+            executable_info(crate::ID),
+            executable_info(crate::ID),
+            executable_info(spl_token::ID),
+            executable_info(system_program::ID),
+        ];
+        let mut accounts: &[AccountInfo<'static>] = Box::leak(infos.into_boxed_slice());
+
+        RescueWithdraw::try_accounts(
+            &crate::ID,
+            &mut accounts,
+            &[],
+            &mut RescueWithdrawBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+    }
+
+    fn new_stake(amount: u64, stake_years: u8) -> UserStake {
+        let mut user_stake: UserStake = zeroed();
+        user_stake
+            .create_stake(
+                Pubkey::new_unique(),
+                amount,
+                stake_years,
+                &clock_at(START, 1),
+                0,
+                254,
+            )
+            .unwrap();
+        user_stake
+    }
+
+    #[test]
+    fn adjustment_within_bounds_is_allowed() {
+        let vault = new_vault();
+        let mut user_stake = new_stake(1_000, 1);
+
+        user_stake
+            .check_adjustment(&vault, 1_500, START + DAY, 2, 2_000)
+            .unwrap();
+        user_stake.adjust(1_500, START + DAY, 2);
+
+        assert_eq!(user_stake.amount, 1_500);
+        assert_eq!(user_stake.unlock_time, START + DAY);
+        assert_eq!(user_stake.get_multiplier(), 2);
+    }
+
+    #[test]
+    fn adjustment_out_of_bounds_is_rejected() {
+        let vault = new_vault();
+        let user_stake = new_stake(1_000, 1);
+        let unlock_time = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake
+                .check_adjustment(&vault, 2_001, unlock_time, 1, 2_000)
+                .unwrap_err(),
+            VaultError::AdjustmentExceedsVaultBalance.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_adjustment(&vault, 1_000, START - 1, 1, 2_000)
+                .unwrap_err(),
+            VaultError::InvalidUnlockTime.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_adjustment(&vault, 0, unlock_time, 1, 2_000)
+                .unwrap_err(),
+            VaultError::InvalidAmount.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_adjustment(&vault, 1_000, unlock_time, 3, 2_000)
+                .unwrap_err(),
+            VaultError::InvalidStakePeriod.into()
+        );
+    }
+
+    #[test]
+    fn beneficiary_requires_minimum_dormancy() {
+        let mut user_stake = new_stake(1_000, 1);
+        let beneficiary = Some(Pubkey::new_unique());
+
+        assert_eq!(
+            user_stake.set_beneficiary(beneficiary, 1).unwrap_err(),
+            VaultError::InvalidDormancyPeriod.into()
+        );
+        user_stake
+            .set_beneficiary(beneficiary, MIN_DORMANCY_PERIOD)
+            .unwrap();
+        user_stake.set_beneficiary(None, 0).unwrap();
+        assert_eq!(user_stake.beneficiary, None);
+    }
+
+    #[test]
+    fn beneficiary_claim_waits_for_dormancy_window() {
+        let mut user_stake = new_stake(1_000, 1);
+        user_stake
+            .set_beneficiary(Some(Pubkey::new_unique()), MIN_DORMANCY_PERIOD)
+            .unwrap();
+        let unlock_time = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake.check_if_dormant(unlock_time - 1).unwrap_err(),
+            VaultError::StillLocked.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_if_dormant(unlock_time + MIN_DORMANCY_PERIOD - 1)
+                .unwrap_err(),
+            VaultError::StakeNotDormant.into()
+        );
+        user_stake
+            .check_if_dormant(unlock_time + MIN_DORMANCY_PERIOD)
+            .unwrap();
+    }
+
+    #[test]
+    fn loyalty_reward_scales_with_lock_years() {
+        assert_eq!(
+            new_stake(1_000, 1).calculate_loyalty_reward(6, 6).unwrap(),
+            1_000
+        );
+        assert_eq!(
+            new_stake(1_000, 2).calculate_loyalty_reward(6, 6).unwrap(),
+            2_000
+        );
+        assert_eq!(
+            new_stake(1_000, 2).calculate_loyalty_reward(6, 9).unwrap(),
+            2_000_000
+        );
+        assert_eq!(
+            new_stake(1_500, 1).calculate_loyalty_reward(9, 6).unwrap(),
+            1
+        );
+        assert_eq!(
+            new_stake(u64::MAX, 2)
+                .calculate_loyalty_reward(6, 6)
+                .unwrap_err(),
+            VaultError::MathOverflow.into()
+        );
+        assert_eq!(
+            new_stake(u64::MAX / 2, 1)
+                .calculate_loyalty_reward(6, 9)
+                .unwrap_err(),
+            VaultError::MathOverflow.into()
+        );
+    }
+
+    #[test]
+    fn stake_rejects_token_accounts_of_differing_mints() {
+        let mint = Pubkey::new_unique();
+        let user_token_account = token_account(mint, Pubkey::new_unique());
+
+        check_same_mint(
+            &user_token_account,
+            &token_account(mint, Pubkey::new_unique()),
+        )
+        .unwrap();
+        assert_eq!(
+            check_same_mint(
+                &user_token_account,
+                &token_account(Pubkey::new_unique(), Pubkey::new_unique()),
+            )
+            .unwrap_err(),
+            VaultError::MintMismatch.into()
+        );
+    }
+
+    #[test]
+    fn installments_vest_on_schedule() {
+        let mut user_stake = new_stake(1_000, 1);
+        user_stake.schedule_installments(100, 3, DAY, START);
+
+        assert_eq!(user_stake.claimable_installments(START).unwrap(), (0, 0));
+        assert_eq!(
+            user_stake.claimable_installments(START + DAY - 1).unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            user_stake.claimable_installments(START + DAY).unwrap(),
+            (1, 33)
+        );
+
+        user_stake.record_installments_paid(1);
+        assert_eq!(
+            user_stake.claimable_installments(START + DAY).unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            user_stake.claimable_installments(START + 10 * DAY).unwrap(),
+            (2, 67)
+        );
+
+        user_stake.record_installments_paid(2);
+        assert_eq!(
+            user_stake.claimable_installments(START + 10 * DAY).unwrap(),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn streak_bonus_escalates_with_completed_stakes() {
+        let mut vault = new_vault();
+        vault.set_streak_bonus(50, 120);
+        let mut user_stats: UserStats = zeroed();
+        let user = Pubkey::new_unique();
+
+        assert_eq!(
+            vault.calculate_streak_bonus_bps(user_stats.current_streak),
+            0
+        );
+        user_stats.record_completed_stake(user, 253);
+        assert_eq!(
+            vault.calculate_streak_bonus_bps(user_stats.current_streak),
+            50
+        );
+        user_stats.record_completed_stake(user, 253);
+        user_stats.record_completed_stake(user, 253);
+        assert_eq!(
+            vault.calculate_streak_bonus_bps(user_stats.current_streak),
+            120
+        );
+        assert_eq!(new_stake(10_000, 1).calculate_bonus(120).unwrap(), 120);
+
+        user_stats.break_streak(user, 253);
+        assert_eq!(
+            vault.calculate_streak_bonus_bps(user_stats.current_streak),
+            0
+        );
+    }
+
+    #[test]
+    fn long_lock_requires_threshold() {
+        let mut vault = new_vault();
+        assert!(!vault.is_long_lock(MAX_STAKE_YEARS));
+
+        vault.set_long_lock_threshold(1);
+        assert!(!vault.is_long_lock(1));
+        assert!(vault.is_long_lock(2));
+    }
+
+    #[test]
+    fn rescue_accepts_reopened_token_account() {
+        try_rescue_to(|mint, user| (mint, user)).unwrap();
+    }
+
+    #[test]
+    fn rescue_validates_destination_mint_and_authority() {
+        assert_eq!(
+            try_rescue_to(|_, user| (Pubkey::new_unique(), user)).unwrap_err(),
+            ErrorCode::ConstraintTokenMint.into()
+        );
+        assert_eq!(
+            try_rescue_to(|mint, _| (mint, Pubkey::new_unique())).unwrap_err(),
+            ErrorCode::ConstraintTokenOwner.into()
+        );
+    }
+
+    #[test]
+    fn next_unlock_is_earliest_open_position() {
+        let user = Pubkey::new_unique();
+        let position = |amount: u64, stake_years: u8, is_withdrawn: bool| {
+            let mut user_stake = new_stake(amount, stake_years);
+            user_stake.user = user;
+            user_stake.is_withdrawn = is_withdrawn;
+            program_account_info(Pubkey::new_unique(), &user_stake)
+        };
+
+        let infos: &'static [AccountInfo<'static>] = Box::leak(
+            vec![
+                position(2_000, 2, false),
+                position(500, 0, true),
+                position(1_000, 1, false),
+            ]
+            .into_boxed_slice(),
+        );
+
+        let next_unlock = find_next_unlock(infos, user).unwrap().unwrap();
+        assert_eq!(next_unlock.user_stake, infos[2].key());
+        assert_eq!(next_unlock.unlock_time, START + SECONDS_PER_YEAR);
+        assert_eq!(next_unlock.amount, 1_000);
+
+        assert!(find_next_unlock(&infos[1..2], user).unwrap().is_none());
+        assert_eq!(
+            find_next_unlock(infos, Pubkey::new_unique()).err(),
+            Some(VaultError::UnauthorizedUser.into())
+        );
+    }
+
+    #[test]
+    fn commitment_settles_on_attestation_or_deadline() {
+        let mut user_stake = new_stake(1_000, 1);
+        assert!(!user_stake.has_failed_commitment());
+        user_stake.check_if_commitment_settled(START).unwrap();
+
+        user_stake.set_commitment_goal(START + DAY);
+        assert_eq!(
+            user_stake
+                .check_if_commitment_settled(START + DAY)
+                .unwrap_err(),
+            VaultError::CommitmentPending.into()
+        );
+        user_stake
+            .check_if_commitment_settled(START + DAY + 1)
+            .unwrap();
+        assert!(user_stake.has_failed_commitment());
+
+        user_stake.mark_goal_succeeded();
+        user_stake.check_if_commitment_settled(START).unwrap();
+        assert!(!user_stake.has_failed_commitment());
+    }
+
+    #[test]
+    fn failed_commitment_forfeits_share_of_principal() {
+        let mut vault = new_vault();
+        vault.set_commitment_forfeit(2_500);
+        let user_stake = new_stake(1_000, 1);
+
+        assert_eq!(
+            user_stake
+                .calculate_bonus(vault.commitment_forfeit_bps as u64)
+                .unwrap(),
+            250
+        );
+    }
+
+    #[test]
+    fn reward_cap_limits_total_return() {
+        let mut vault = new_vault();
+        let user_stake = new_stake(1_000, 2);
+        let unlock_time = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake
+                .calculate_total_return(1_000, &vault, unlock_time)
+                .unwrap(),
+            2_100
+        );
+
+        vault.set_max_reward_per_stake(400);
+        assert_eq!(
+            user_stake
+                .calculate_total_return(1_000, &vault, unlock_time)
+                .unwrap(),
+            1_400
+        );
+    }
+
+    #[test]
+    fn committed_reward_includes_locked_in_bonuses() {
+        let mut vault = new_vault();
+        let mut user_stake = new_stake(1_000, 2);
+        assert_eq!(
+            user_stake.calculate_committed_reward(&vault).unwrap(),
+            1_000
+        );
+
+        user_stake.set_max_lock_bonus(500);
+        user_stake.set_curve_bonus(250);
+        assert_eq!(
+            user_stake.calculate_committed_reward(&vault).unwrap(),
+            1_075
+        );
+
+        vault.set_max_reward_per_stake(600);
+        assert_eq!(user_stake.calculate_committed_reward(&vault).unwrap(), 600);
+        assert_eq!(
+            new_stake(1_000, 1)
+                .calculate_committed_reward(&vault)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn reserve_buffer_rejects_stake_that_stays_solvent() {
+        let mut vault = new_vault();
+        vault.add_staked(1_000).unwrap();
+        vault.commit_reward(1_000).unwrap();
+        let reward_reserve = vault.reward_reserve(3_500);
+        assert_eq!(reward_reserve, 2_500);
+
+        vault.check_reserve_buffer(reward_reserve, 1_500).unwrap();
+
+        vault.set_reserve_buffer(12_000);
+        vault.check_reserve_buffer(reward_reserve, 1_000).unwrap();
+        assert_eq!(
+            vault
+                .check_reserve_buffer(reward_reserve, 1_500)
+                .unwrap_err(),
+            VaultError::InsufficientReserveBuffer.into()
+        );
+
+        vault.release_committed_reward(1_000);
+        vault.check_reserve_buffer(reward_reserve, 1_500).unwrap();
+    }
+
+    #[test]
+    fn cohort_shares_follow_amount_times_time_staked() {
+        let mut vault = new_vault();
+        let mut early = new_stake(1_000, 1);
+        let mut late = new_stake(3_000, 1);
+
+        early.join_cohort(START);
+        vault.add_cohort_member(&early, START).unwrap();
+        late.join_cohort(START + 3 * DAY);
+        vault.add_cohort_member(&late, START + 3 * DAY).unwrap();
+
+        vault.close_cohort(700, START + 4 * DAY).unwrap();
+
+        let early_weight = early.cohort_weight(vault.cohort_closed_at);
+        let late_weight = late.cohort_weight(vault.cohort_closed_at);
+        assert_eq!(early_weight, 1_000 * 4 * DAY as u128);
+        assert_eq!(late_weight, 3_000 * DAY as u128);
+        assert_eq!(vault.cohort_total_weight, early_weight + late_weight);
+        assert_eq!(vault.calculate_cohort_share(early_weight).unwrap(), 400);
+        assert_eq!(vault.calculate_cohort_share(late_weight).unwrap(), 300);
+    }
+
+    #[test]
+    fn cohort_keeps_weight_earned_before_resize_and_drops_leavers() {
+        let mut vault = new_vault();
+        let mut resized = new_stake(1_000, 1);
+        let mut leaver = new_stake(5_000, 1);
+
+        resized.join_cohort(START);
+        vault.add_cohort_member(&resized, START).unwrap();
+        leaver.join_cohort(START);
+        vault.add_cohort_member(&leaver, START).unwrap();
+
+        vault.remove_cohort_member(&resized).unwrap();
+        resized.compound(1_000).unwrap();
+        resized.resize_cohort(START + DAY);
+        vault.add_cohort_member(&resized, START + DAY).unwrap();
+
+        vault.remove_cohort_member(&leaver).unwrap();
+        leaver.leave_cohort();
+        assert_eq!(vault.cohort_subscribed, 2_000);
+
+        vault.close_cohort(300, START + 2 * DAY).unwrap();
+
+        let weight = resized.cohort_weight(vault.cohort_closed_at);
+        assert_eq!(weight, (1_000 + 2_000) * DAY as u128);
+        assert_eq!(vault.cohort_total_weight, weight);
+        assert_eq!(vault.calculate_cohort_share(weight).unwrap(), 300);
+    }
+
+    #[test]
+    fn event_flags_gate_each_category() {
+        let mut vault = new_vault();
+        assert!(vault.emits(EVENT_STAKE) && vault.emits(EVENT_NOTIFY));
+
+        vault.set_event_flags(EVENT_WITHDRAW | EVENT_ADMIN);
+        assert!(vault.emits(EVENT_WITHDRAW));
+        assert!(vault.emits(EVENT_ADMIN));
+        assert!(!vault.emits(EVENT_STAKE));
+        assert!(!vault.emits(EVENT_CLAIM));
+
+        vault.set_event_flags(0);
+        assert!(!vault.emits(EVENT_ALL));
+    }
+
+    #[test]
+    fn withdrawal_rejected_in_staking_slot() {
+        let user_stake = new_stake(1_000, 1);
+
+        assert_eq!(
+            user_stake.check_not_same_slot(1).unwrap_err(),
+            VaultError::SameSlotWithdrawal.into()
+        );
+        user_stake.check_not_same_slot(2).unwrap();
+    }
+
+    #[test]
+    fn stake_activates_after_settlement_delay() {
+        let mut user_stake: UserStake = zeroed();
+        user_stake
+            .create_stake(
+                Pubkey::new_unique(),
+                1_000,
+                1,
+                &clock_at(START, 1),
+                DAY,
+                254,
+            )
+            .unwrap();
+
+        assert_eq!(user_stake.active_from, START + DAY);
+        assert_eq!(user_stake.unlock_time, START + DAY + SECONDS_PER_YEAR);
+        assert_eq!(
+            user_stake.check_if_active(START + DAY - 1).unwrap_err(),
+            VaultError::NotYetActive.into()
+        );
+        user_stake.check_if_active(START + DAY).unwrap();
+    }
+
+    #[test]
+    fn maturity_warning_fires_once_inside_window() {
+        let mut user_stake = new_stake(1_000, 1);
+        let unlock_time = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake
+                .check_if_in_notification_window(unlock_time)
+                .unwrap_err(),
+            VaultError::NoMaturityNotification.into()
+        );
+
+        user_stake.set_maturity_notification(7 * DAY);
+        assert_eq!(
+            user_stake
+                .check_if_in_notification_window(unlock_time - 7 * DAY - 1)
+                .unwrap_err(),
+            VaultError::NotInNotificationWindow.into()
+        );
+        user_stake
+            .check_if_in_notification_window(unlock_time - 7 * DAY)
+            .unwrap();
+
+        user_stake.mark_as_warned();
+        assert_eq!(
+            user_stake
+                .check_if_in_notification_window(unlock_time)
+                .unwrap_err(),
+            VaultError::MaturityAlreadyWarned.into()
+        );
+    }
+
+    #[test]
+    fn reward_ramps_in_linearly() {
+        let user_stake = new_stake(1_000, 1);
+
+        assert_eq!(
+            user_stake.apply_reward_ramp(1_000, 0, START).unwrap(),
+            1_000
+        );
+        assert_eq!(
+            user_stake.apply_reward_ramp(1_000, 4 * DAY, START).unwrap(),
+            0
+        );
+        assert_eq!(
+            user_stake
+                .apply_reward_ramp(1_000, 4 * DAY, START + DAY)
+                .unwrap(),
+            250
+        );
+        assert_eq!(
+            user_stake
+                .apply_reward_ramp(1_000, 4 * DAY, START + 4 * DAY)
+                .unwrap(),
+            1_000
+        );
+    }
+
+    #[test]
+    fn reward_ledger_wraps_around() {
+        let mut ledger: RewardLedger = zeroed();
+        ledger.initialize(Pubkey::new_unique(), 252);
+        let user = Pubkey::new_unique();
+
+        for i in 0..REWARD_LEDGER_CAPACITY as u64 + 2 {
+            ledger.record(RewardLedgerEntry {
+                user,
+                amount: i,
+                timestamp: START,
+            });
+        }
+
+        assert_eq!(ledger.entries.len(), REWARD_LEDGER_CAPACITY);
+        assert_eq!(ledger.total_entries, REWARD_LEDGER_CAPACITY as u64 + 2);
+        assert_eq!(ledger.next_index, 2);
+        assert_eq!(ledger.entries[0].amount, REWARD_LEDGER_CAPACITY as u64);
+        assert_eq!(ledger.entries[1].amount, REWARD_LEDGER_CAPACITY as u64 + 1);
+        assert_eq!(ledger.entries[2].amount, 2);
+    }
+
+    #[test]
+    fn epoch_bonus_uses_lowest_balance_during_epoch() {
+        let mut vault = new_vault();
+        vault.set_loyalty_epoch(START + DAY, START + 3 * DAY, 800, 100);
+        let mut user_stake = new_stake(1_000, 1);
+
+        user_stake.adjust(1_200, user_stake.unlock_time, 1);
+        user_stake.update_epoch_floor(&vault, START + DAY / 2);
+        assert_eq!(user_stake.epoch_floor, 1_200);
+
+        user_stake.adjust(700, user_stake.unlock_time, 1);
+        user_stake.update_epoch_floor(&vault, START + 2 * DAY);
+        user_stake.adjust(2_000, user_stake.unlock_time, 1);
+        user_stake.update_epoch_floor(&vault, START + 2 * DAY);
+        assert_eq!(user_stake.epoch_floor, 700);
+        assert!(!user_stake.qualifies_for_epoch_bonus(&vault, START + 3 * DAY));
+
+        user_stake.update_epoch_floor(&vault, START + 4 * DAY);
+        assert_eq!(user_stake.epoch_floor, 700);
+
+        let held = new_stake(1_000, 1);
+        assert!(!held.qualifies_for_epoch_bonus(&vault, START + 3 * DAY - 1));
+        assert!(held.qualifies_for_epoch_bonus(&vault, START + 3 * DAY));
+    }
+
+    #[test]
+    fn withdrawal_bonus_sums_locked_in_and_live_bonuses() {
+        let mut vault = new_vault();
+        vault.set_veteran_bonus(100, 10_000);
+        let mut user_stake = new_stake(10_000, 2);
+        user_stake.set_max_lock_bonus(300);
+        user_stake.set_curve_bonus(40);
+        user_stake.set_streak_bonus(50);
+        user_stake
+            .set_veteran_bonus(vault.calculate_veteran_bonus_bps(2 * SECONDS_PER_YEAR) as u16);
+        let now = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake.calculate_withdrawal_bonus_bps(&vault, now),
+            50 + 200 + 300 + 40
+        );
+        assert_eq!(
+            user_stake.calculate_total_return(590, &vault, now).unwrap(),
+            20_590
+        );
+
+        user_stake.set_commitment_goal(START + DAY);
+        assert_eq!(user_stake.calculate_withdrawal_bonus_bps(&vault, now), 0);
+    }
+
+    #[test]
+    fn max_lock_bonus_only_for_longest_lock() {
+        let mut vault = new_vault();
+        vault.set_max_lock_bonus(500);
+
+        assert_eq!(vault.max_lock_bonus_for(MAX_STAKE_YEARS), 500);
+        assert_eq!(vault.max_lock_bonus_for(1), 0);
+
+        let mut user_stake = new_stake(10_000, MAX_STAKE_YEARS);
+        user_stake.set_max_lock_bonus(vault.max_lock_bonus_for(MAX_STAKE_YEARS));
+        vault.set_max_lock_bonus(0);
+        assert_eq!(
+            user_stake.calculate_withdrawal_bonus_bps(&vault, user_stake.unlock_time),
+            500
+        );
+    }
+
+    #[test]
+    fn flexible_stake_earns_no_bonus() {
+        let mut vault = new_vault();
+        vault.set_allow_flexible(true);
+        vault.set_veteran_bonus(100, 10_000);
+        let mut user_stake = new_stake(10_000, 0);
+        user_stake.set_curve_bonus(300);
+        user_stake.set_streak_bonus(50);
+
+        assert!(!user_stake.earns_bonus());
+        assert_eq!(user_stake.calculate_withdrawal_bonus_bps(&vault, START), 0);
+        assert_eq!(user_stake.calculate_committed_reward(&vault).unwrap(), 0);
+        assert_eq!(
+            user_stake.calculate_total_return(0, &vault, START).unwrap(),
+            10_000
+        );
+    }
+
+    #[test]
+    fn collateral_check_binds_owner_amount_and_unlock() {
+        let mut user_stake = new_stake(1_000, 1);
+        let user = user_stake.user;
+        let unlock_time = user_stake.unlock_time;
+
+        user_stake
+            .check_collateral(user, 1_000, unlock_time)
+            .unwrap();
+        assert_eq!(
+            user_stake
+                .check_collateral(Pubkey::new_unique(), 1_000, unlock_time)
+                .unwrap_err(),
+            VaultError::UnauthorizedUser.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_collateral(user, 1_001, unlock_time)
+                .unwrap_err(),
+            VaultError::InsufficientCollateral.into()
+        );
+        assert_eq!(
+            user_stake
+                .check_collateral(user, 1_000, unlock_time + 1)
+                .unwrap_err(),
+            VaultError::CollateralUnlocksTooSoon.into()
+        );
+
+        user_stake.mark_as_withdrawn();
+        assert_eq!(
+            user_stake.check_collateral(user, 0, 0).unwrap_err(),
+            VaultError::AlreadyWithdrawn.into()
+        );
+    }
+
+    #[test]
+    fn reward_decays_after_claim_window() {
+        let mut vault = new_vault();
+        let user_stake = new_stake(1_000, 1);
+        let unlock_time = user_stake.unlock_time;
+
+        assert_eq!(
+            user_stake
+                .apply_reward_decay(1_000, &vault, unlock_time + 100 * DAY)
+                .unwrap(),
+            1_000
+        );
+
+        vault.set_reward_decay(10 * DAY, 4 * DAY);
+        let decay_start = unlock_time + 10 * DAY;
+        assert_eq!(
+            user_stake
+                .apply_reward_decay(1_000, &vault, decay_start)
+                .unwrap(),
+            1_000
+        );
+        assert_eq!(
+            user_stake
+                .apply_reward_decay(1_000, &vault, decay_start + DAY)
+                .unwrap(),
+            750
+        );
+        assert_eq!(
+            user_stake
+                .apply_reward_decay(1_000, &vault, decay_start + 4 * DAY)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn veteran_bonus_counts_full_years_of_tenure() {
+        let mut vault = new_vault();
+        vault.set_veteran_bonus(100, 250);
+        let mut user_profile: UserProfile = zeroed();
+        let user = Pubkey::new_unique();
+
+        user_profile.record_stake(user, 251, START);
+        user_profile.record_stake(user, 251, START + DAY);
+        assert_eq!(user_profile.first_stake_ever, START);
+
+        let tenure = user_profile.tenure(START + 2 * SECONDS_PER_YEAR - 1);
+        assert_eq!(vault.calculate_veteran_bonus_bps(tenure), 100);
+        let tenure = user_profile.tenure(START + 2 * SECONDS_PER_YEAR);
+        assert_eq!(vault.calculate_veteran_bonus_bps(tenure), 200);
+        let tenure = user_profile.tenure(START + 40 * SECONDS_PER_YEAR);
+        assert_eq!(vault.calculate_veteran_bonus_bps(tenure), 250);
+        assert_eq!(user_profile.tenure(START - 1), 0);
+    }
+
+    #[test]
+    fn reward_split_follows_weights_and_sums_exactly() {
+        assert_eq!(
+            split_by_weights(1_001, &[5_000, 3_000, 2_000]).unwrap(),
+            vec![500, 300, 201]
+        );
+        assert_eq!(split_by_weights(7, &[10_000]).unwrap(), vec![7]);
+        assert_eq!(
+            split_by_weights(1_000, &[5_000, 4_999]).unwrap_err(),
+            VaultError::InvalidAllocationWeights.into()
+        );
+    }
+
+    #[test]
+    fn congestion_bonus_requires_holding_through_window() {
+        let mut vault = new_vault();
+        let held = new_stake(1_000, 1);
+        assert!(!held.qualifies_for_congestion_bonus(&vault, START + DAY));
+
+        vault.activate_congestion_bonus(START, DAY, 200).unwrap();
+        assert!(!held.qualifies_for_congestion_bonus(&vault, START + DAY - 1));
+        assert!(held.qualifies_for_congestion_bonus(&vault, START + DAY));
+
+        let mut late: UserStake = zeroed();
+        late.create_stake(
+            Pubkey::new_unique(),
+            1_000,
+            1,
+            &clock_at(START + 1, 2),
+            0,
+            254,
+        )
+        .unwrap();
+        assert!(!late.qualifies_for_congestion_bonus(&vault, START + DAY));
+    }
+
+    #[test]
+    fn reserve_snapshot_counts_every_liability() {
+        let mut vault = new_vault();
+        vault.add_staked(10_000).unwrap();
+        vault.accrued_management_fee = 100;
+        vault.commit_reward(2_000).unwrap();
+        vault.add_outstanding_payout(900).unwrap();
+
+        let snapshot = vault.reserve_snapshot(15_000, &clock_at(START, 9)).unwrap();
+        assert_eq!(snapshot.reward_reserve, 4_000);
+        assert_eq!(snapshot.committed_rewards, 2_000);
+        assert_eq!(snapshot.outstanding_payouts, 900);
+        assert_eq!(snapshot.insurance_balance, 2_000);
+        assert_eq!(snapshot.collateralization_bps, 15_000 * 10_000 / 13_000);
+
+        vault.settle_outstanding_payout(900);
+        let snapshot = vault
+            .reserve_snapshot(14_100, &clock_at(START, 10))
+            .unwrap();
+        assert_eq!(snapshot.reward_reserve, 4_000);
+    }
+
+    #[test]
+    fn closed_cohort_pool_is_outstanding_until_claimed() {
+        let mut vault = new_vault();
+        vault.set_cohort_target(1_000, START + DAY, 100, START);
+        let mut member = new_stake(1_000, 1);
+        member.join_cohort(START);
+        vault.add_cohort_member(&member, START).unwrap();
+
+        vault.close_cohort(500, START + 2 * DAY).unwrap();
+        assert_eq!(vault.outstanding_payouts, 510);
+    }
+
+    #[test]
+    fn management_fee_claim_capped_at_surplus() {
+        let mut vault = new_vault();
+        vault.add_staked(10_000).unwrap();
+        vault.commit_reward(1_000).unwrap();
+        vault.add_outstanding_payout(500).unwrap();
+        vault.accrued_management_fee = 300;
+
+        assert_eq!(vault.claimable_management_fee(12_000), 300);
+        assert_eq!(vault.claimable_management_fee(11_600), 100);
+        assert_eq!(vault.claimable_management_fee(11_000), 0);
+
+        vault.deduct_management_fee(100);
+        assert_eq!(vault.accrued_management_fee, 200);
+    }
+
+    #[test]
+    fn management_fee_accrues_on_total_staked() {
+        let mut vault = new_vault();
+        vault.set_management_fee(100);
+        vault.add_staked(1_000_000).unwrap();
+
+        vault
+            .accrue_management_fee(START + SECONDS_PER_YEAR / 2)
+            .unwrap();
+        assert_eq!(vault.accrued_management_fee, 5_000);
+        assert_eq!(vault.last_fee_accrual, START + SECONDS_PER_YEAR / 2);
+    }
+
+    #[test]
+    fn savings_release_after_lock() {
+        let mut savings_vault: SavingsVault = zeroed();
+        let vault = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        savings_vault.initialize_if_needed(vault, user, 250);
+
+        assert_eq!(
+            savings_vault.release(START).unwrap_err(),
+            VaultError::NoSavingsToRelease.into()
+        );
+
+        savings_vault.deposit(100, START, 10 * DAY).unwrap();
+        savings_vault.deposit(50, START + DAY, DAY).unwrap();
+        assert_eq!(savings_vault.release_time, START + 10 * DAY);
+
+        savings_vault.initialize_if_needed(vault, user, 250);
+        assert_eq!(savings_vault.amount, 150);
+
+        assert_eq!(
+            savings_vault.release(START + 10 * DAY - 1).unwrap_err(),
+            VaultError::SavingsStillLocked.into()
+        );
+        assert_eq!(savings_vault.release(START + 10 * DAY).unwrap(), 150);
+        assert_eq!(savings_vault.amount, 0);
+    }
+
+    #[test]
+    fn guardian_must_cosign_once_set() {
+        let mut stake = new_stake(1_000, 1);
+        stake.check_guardian(None).unwrap();
+
+        let guardian = Pubkey::new_unique();
+        stake.set_guardian(Some(guardian));
+        assert_eq!(
+            stake.check_guardian(None).unwrap_err(),
+            VaultError::GuardianSignatureRequired.into()
+        );
+        assert_eq!(
+            stake
+                .check_guardian(Some(Pubkey::new_unique()))
+                .unwrap_err(),
+            VaultError::GuardianSignatureRequired.into()
+        );
+        stake.check_guardian(Some(guardian)).unwrap();
+    }
+
+    #[test]
+    fn merkle_campaign_must_be_funded() {
+        let mut vault = new_vault();
+        vault.add_staked(10_000).unwrap();
+        vault.commit_reward(1_000).unwrap();
+
+        assert_eq!(
+            vault.fund_outstanding_payout(12_000, 1_001).unwrap_err(),
+            VaultError::InsufficientRewardReserve.into()
+        );
+        vault.fund_outstanding_payout(12_000, 1_000).unwrap();
+        assert_eq!(vault.outstanding_payouts, 1_000);
+        assert_eq!(
+            vault.fund_outstanding_payout(12_000, 1).unwrap_err(),
+            VaultError::InsufficientRewardReserve.into()
+        );
+    }
+
+    fn merkle_leaf(index: u32, user: &Pubkey, amount: u64) -> [u8; 32] {
+        keccak::hashv(&[&index.to_le_bytes(), user.as_ref(), &amount.to_le_bytes()]).0
+    }
+
+    #[test]
+    fn merkle_proof_verifies_sorted_pairs() {
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        let left = merkle_leaf(0, &alice, 100);
+        let right = merkle_leaf(1, &bob, 200);
+        let root = if left <= right {
+            keccak::hashv(&[&left, &right]).0
+        } else {
+            keccak::hashv(&[&right, &left]).0
+        };
+
+        let mut campaign: MerkleCampaign = zeroed();
+        campaign.start(Pubkey::new_unique(), root, 300, 253);
+
+        assert!(campaign.verify_proof(&[right], left));
+        assert!(campaign.verify_proof(&[left], right));
+        assert!(!campaign.verify_proof(&[right], merkle_leaf(0, &alice, 101)));
+        assert!(!campaign.verify_proof(&[], left));
+    }
+
+    #[test]
+    fn merkle_leaf_claims_once_within_allocation() {
+        let mut campaign: MerkleCampaign = zeroed();
+        campaign.start(Pubkey::new_unique(), [0; 32], 300, 253);
+
+        campaign.claim(0, 100).unwrap();
+        assert_eq!(campaign.remaining_allocation, 200);
+        assert_eq!(
+            campaign.claim(0, 100).unwrap_err(),
+            VaultError::MerkleBonusAlreadyClaimed.into()
+        );
+        assert_eq!(
+            campaign.claim(1, 201).unwrap_err(),
+            VaultError::CampaignAllocationExceeded.into()
+        );
+        assert_eq!(
+            campaign
+                .claim((MERKLE_BITMAP_BYTES * 8) as u32, 1)
+                .unwrap_err(),
+            VaultError::MerkleIndexOutOfRange.into()
+        );
+        campaign.claim(1, 200).unwrap();
+        assert_eq!(campaign.remaining_allocation, 0);
+    }
+
+    #[test]
+    fn held_payout_releases_to_recorded_destination() {
+        let mut stake = new_stake(1_000, 1);
+        let destination = Pubkey::new_unique();
+        stake.hold_payout(1_100, 100, destination, START + DAY);
+        assert_eq!(stake.payout_destination, destination);
+
+        assert_eq!(
+            stake.release_payout(START + DAY - 1).unwrap_err(),
+            VaultError::DisputeWindowOpen.into()
+        );
+        stake.set_disputed(true);
+        assert_eq!(
+            stake.release_payout(START + DAY).unwrap_err(),
+            VaultError::PayoutDisputed.into()
+        );
+        stake.set_disputed(false);
+
+        assert_eq!(stake.release_payout(START + DAY).unwrap(), (1_100, 100));
+        assert_eq!(stake.pending_reward, 0);
+        assert_eq!(
+            stake.release_payout(START + DAY).unwrap_err(),
+            VaultError::NoPendingPayout.into()
+        );
+    }
+
+    #[test]
+    fn rejected_payout_hands_back_the_held_amount() {
+        let mut stake = new_stake(1_000, 1);
+        stake.mark_as_withdrawn();
+        stake.hold_payout(1_100, 100, Pubkey::new_unique(), START + DAY);
+        assert!(!stake.is_reusable());
+
+        assert_eq!(
+            stake.reject_payout().unwrap_err(),
+            VaultError::PayoutNotDisputed.into()
+        );
+        stake.set_disputed(true);
+        assert_eq!(stake.reject_payout().unwrap(), (1_000, 100));
+        assert_eq!(stake.pending_payout, 0);
+        assert_eq!(stake.pending_reward, 0);
+        assert!(!stake.disputed);
+        assert!(stake.is_reusable());
+    }
+
+    #[test]
+    fn min_stake_years_scale_with_amount() {
+        let mut vault = new_vault();
+        assert_eq!(vault.min_stake_years_for(u64::MAX), 0);
+
+        vault.set_min_lock_for_amount(1_000, 5_000);
+        assert_eq!(vault.min_stake_years_for(999), 0);
+        assert_eq!(vault.min_stake_years_for(1_000), 1);
+        assert_eq!(vault.min_stake_years_for(4_999), 1);
+        assert_eq!(vault.min_stake_years_for(5_000), 2);
+
+        vault.set_min_lock_for_amount(1_000, 0);
+        assert_eq!(vault.min_stake_years_for(u64::MAX), 1);
+
+        vault.set_min_lock_for_amount(0, 5_000);
+        assert_eq!(vault.min_stake_years_for(4_999), 0);
+        assert_eq!(vault.min_stake_years_for(5_000), 2);
+    }
+
+    #[test]
+    fn keeper_tip_is_share_of_reward() {
+        let mut vault = new_vault();
+        assert_eq!(vault.calculate_keeper_tip(1_000).unwrap(), 0);
+
+        vault.set_keeper_tip(50);
+        assert_eq!(vault.calculate_keeper_tip(1_000).unwrap(), 5);
+        assert_eq!(vault.calculate_keeper_tip(199).unwrap(), 0);
+        assert_eq!(vault.calculate_keeper_tip(0).unwrap(), 0);
+
+        vault.set_keeper_tip(10_000);
+        assert_eq!(vault.calculate_keeper_tip(u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn keeper_withdraw_requires_opt_in_and_no_guardian() {
+        let mut stake = new_stake(1_000, 1);
+        assert_eq!(
+            stake.check_auto_withdraw().unwrap_err(),
+            VaultError::AutoWithdrawNotEnabled.into()
+        );
+
+        stake.set_auto_withdraw(true);
+        stake.check_auto_withdraw().unwrap();
+
+        stake.set_guardian(Some(Pubkey::new_unique()));
+        assert_eq!(
+            stake.check_guardian(None).unwrap_err(),
+            VaultError::GuardianSignatureRequired.into()
+        );
+    }
+
+    #[test]
+    fn filled_cohort_pays_bonus_to_every_member() {
+        let mut vault = new_vault();
+        vault.set_cohort_target(2_000, START + DAY, 500, START);
+        let mut first = new_stake(1_000, 1);
+        let mut second = new_stake(1_000, 1);
+
+        first.join_cohort(START);
+        vault.add_cohort_member(&first, START).unwrap();
+        assert!(!vault.is_cohort_fully_subscribed());
+        second.join_cohort(START);
+        vault.add_cohort_member(&second, START).unwrap();
+        assert!(vault.is_cohort_fully_subscribed());
+
+        vault.close_cohort(400, START + 2 * DAY).unwrap();
+        assert_eq!(vault.cohort_liability().unwrap(), 500);
+        assert_eq!(vault.calculate_cohort_claim(&first).unwrap(), 250);
+        assert_eq!(vault.calculate_cohort_claim(&second).unwrap(), 250);
+    }
+
+    #[test]
+    fn under_subscribed_cohort_pays_no_bonus() {
+        let mut vault = new_vault();
+        vault.set_cohort_target(2_000, START + DAY, 500, START);
+        let mut stayer = new_stake(1_000, 1);
+        let mut leaver = new_stake(1_000, 1);
+
+        stayer.join_cohort(START);
+        vault.add_cohort_member(&stayer, START).unwrap();
+        leaver.join_cohort(START);
+        vault.add_cohort_member(&leaver, START).unwrap();
+        assert!(vault.is_cohort_fully_subscribed());
+
+        vault.remove_cohort_member(&leaver).unwrap();
+        vault.update_cohort_fill(START + 1);
+        leaver.leave_cohort();
+        assert_eq!(vault.cohort_filled_at, 0);
+
+        vault.close_cohort(400, START + 2 * DAY).unwrap();
+        assert!(!vault.is_cohort_fully_subscribed());
+        assert_eq!(vault.cohort_liability().unwrap(), 400);
+        assert_eq!(vault.calculate_cohort_claim(&stayer).unwrap(), 400);
+    }
+
+    #[test]
+    fn cohort_refilled_after_deadline_stays_unfilled() {
+        let mut vault = new_vault();
+        vault.set_cohort_target(1_000, START + DAY, 500, START);
+        let mut member = new_stake(1_000, 1);
+        member.join_cohort(START);
+        vault.add_cohort_member(&member, START).unwrap();
+
+        vault.remove_cohort_member(&member).unwrap();
+        member.compound(500).unwrap();
+        member.resize_cohort(START + 1);
+        vault.add_cohort_member(&member, START + 1).unwrap();
+        assert_eq!(vault.cohort_filled_at, START);
+
+        let mut late = new_stake(1_000, 1);
+        vault.remove_cohort_member(&member).unwrap();
+        vault.update_cohort_fill(START + 2);
+        late.join_cohort(START + 2 * DAY);
+        vault.add_cohort_member(&late, START + 2 * DAY).unwrap();
+        assert_eq!(vault.cohort_filled_at, START + 2 * DAY);
+        assert!(!vault.is_cohort_fully_subscribed());
+    }
+
+    #[test]
+    fn clock_check_allows_tolerance_plus_drift_rate() {
+        let mut vault = new_vault();
+        let anchor = clock_at(START, 1_000);
+        vault.set_clock_tolerance(60, 0, &anchor);
+
+        let day_slots = (DAY * 1000 / MS_PER_SLOT) as u64;
+        vault.check_clock(&clock_at(START + 60, 1_000)).unwrap();
+        assert_eq!(
+            vault.check_clock(&clock_at(START + 61, 1_000)).unwrap_err(),
+            VaultError::ClockUnreliable.into()
+        );
+        assert_eq!(
+            vault
+                .check_clock(&clock_at(START + DAY + DAY / 10, 1_000 + day_slots))
+                .unwrap_err(),
+            VaultError::ClockUnreliable.into()
+        );
+
+        vault.set_clock_tolerance(60, 1_000, &anchor);
+        vault
+            .check_clock(&clock_at(START + DAY + DAY / 10 + 60, 1_000 + day_slots))
+            .unwrap();
+        vault
+            .check_clock(&clock_at(START + DAY - DAY / 10, 1_000 + day_slots))
+            .unwrap();
+        assert_eq!(
+            vault
+                .check_clock(&clock_at(START + DAY + DAY / 10 + 61, 1_000 + day_slots))
+                .unwrap_err(),
+            VaultError::ClockUnreliable.into()
+        );
+    }
+
+    #[test]
+    fn clock_check_disabled_without_tolerance() {
+        let mut vault = new_vault();
+        vault.set_clock_tolerance(0, 0, &clock_at(START, 1_000));
+        vault
+            .check_clock(&clock_at(START + 10 * DAY, 1_001))
+            .unwrap();
+    }
+
+    #[test]
+    fn reward_rounds_down_once_after_ramp() {
+        let mut vault = new_vault();
+        vault.set_reward_ramp(3);
+        let user_stake = new_stake(3, 2);
+        let now = user_stake.active_from + 2;
+
+        let exact = user_stake
+            .calculate_reward_scaled(5_000, &vault, now)
+            .unwrap();
+        assert_eq!(exact, 3 * 15_000 * 2 / 3);
+        assert_eq!(
+            user_stake
+                .calculate_total_return(5_000, &vault, now)
+                .unwrap(),
+            3 + 3
+        );
+    }
+
+    #[test]
+    fn exact_reward_reflects_decay_and_cap() {
+        let mut vault = new_vault();
+        let user_stake = new_stake(1_001, 1);
+        let unlock_time = user_stake.unlock_time;
+
+        let exact = user_stake
+            .calculate_reward_scaled(333, &vault, unlock_time)
+            .unwrap();
+        let paid = user_stake
+            .calculate_total_return(333, &vault, unlock_time)
+            .unwrap()
+            - 1_001;
+        assert_eq!(exact, 333_333);
+        assert_eq!(exact, paid as u128 * BPS_DENOMINATOR as u128 + 3_333);
+
+        vault.set_reward_decay(0, 4 * DAY);
+        assert_eq!(
+            user_stake
+                .calculate_reward_scaled(333, &vault, unlock_time + 3 * DAY)
+                .unwrap(),
+            333_333 / 4
+        );
+
+        vault.set_reward_decay(0, 0);
+        vault.set_max_reward_per_stake(20);
+        assert_eq!(
+            user_stake
+                .calculate_reward_scaled(333, &vault, unlock_time)
+                .unwrap(),
+            20 * BPS_DENOMINATOR as u128
+        );
+    }
+
+    #[test]
+    fn bonding_curve_averages_over_each_deposit() {
+        let mut vault = new_vault();
+        vault.set_bonding_curve(1_000, 10_000);
+
+        assert_eq!(vault.calculate_curve_bonus_bps(10_000), 500);
+
+        let mut early = new_stake(5_000, 1);
+        vault.apply_bonding_curve(&mut early).unwrap();
+        let mut late = new_stake(5_000, 1);
+        vault.apply_bonding_curve(&mut late).unwrap();
+        assert_eq!(early.curve_bonus_bps, 750);
+        assert_eq!(late.curve_bonus_bps, 250);
+        assert!(early.curve_bonus_bps > late.curve_bonus_bps);
+        assert_eq!(vault.curve_deposited, 10_000);
+        assert_eq!(vault.calculate_curve_bonus_bps(1_000), 0);
+
+        vault.set_bonding_curve(1_000, 12_000);
+        assert_eq!(vault.curve_deposited, 10_000);
+        assert_eq!(vault.calculate_curve_bonus_bps(4_000), 41);
+    }
+
+    #[test]
+    fn flexible_stake_skips_bonding_curve() {
+        let mut vault = new_vault();
+        vault.set_allow_flexible(true);
+        vault.set_bonding_curve(1_000, 10_000);
+
+        let mut flexible = new_stake(5_000, 0);
+        vault.apply_bonding_curve(&mut flexible).unwrap();
+        assert_eq!(flexible.curve_bonus_bps, 0);
+        assert_eq!(vault.curve_deposited, 0);
+    }
+
+    fn resolve_dispute_infos(
+        test_vault: &TestVault,
+        user_stake_key: Pubkey,
+        user_stake: &UserStake,
+        user_token_account: &AccountInfo<'static>,
+    ) -> Vec<AccountInfo<'static>> {
+        vec![
+            test_vault.info(),
+            program_account_info(user_stake_key, user_stake),
+            account_info(user_stake.user, system_program::ID, Vec::new()),
+            test_vault.stats_info(user_stake.user, 3),
+            signer_info(test_vault.admin),
+            user_token_account.clone(),
+            test_vault.token_account.clone(),
+            mint_info(test_vault.mint),
+            executable_info(spl_token::ID),
+        ]
+    }
+
+    #[test]
+    fn rejected_dispute_refunds_principal_and_keeps_reward() {
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut test_vault = TestVault::new(mint, 5_000);
+        test_vault.vault.add_outstanding_payout(1_100).unwrap();
+
+        let (user_stake_key, mut user_stake) = test_vault.stake_for(user, 1_000, 1);
+        user_stake.mark_as_withdrawn();
+        let user_token_account =
+            token_account_info(get_associated_token_address(&user, &mint), mint, user);
+        user_stake.hold_payout(1_100, 100, *user_token_account.key, START + DAY);
+        user_stake.set_disputed(true);
+
+        let infos = resolve_dispute_infos(
+            &test_vault,
+            user_stake_key,
+            &user_stake,
+            &user_token_account,
+        );
+        run_instruction!(ResolveDispute, resolve_dispute, infos, false).unwrap();
+
+        assert_eq!(token_balance(&user_token_account), 1_000);
+        assert_eq!(token_balance(&test_vault.token_account), 4_000);
+
+        let vault: Vault = load(&infos[0]);
+        let user_stake: UserStake = load(&infos[1]);
+        assert_eq!(load::<UserStats>(&infos[3]).current_streak, 0);
+        assert_eq!(vault.outstanding_payouts, 0);
+        assert_eq!(vault.reward_reserve(4_000), 4_000);
+        assert_eq!(user_stake.pending_payout, 0);
+        assert!(user_stake.is_reusable());
+    }
+
+    #[test]
+    fn dispute_refund_only_goes_to_the_stakers_account() {
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let test_vault = TestVault::new(mint, 5_000);
+
+        let (user_stake_key, mut user_stake) = test_vault.stake_for(user, 1_000, 1);
+        user_stake.mark_as_withdrawn();
+        user_stake.hold_payout(1_100, 100, Pubkey::new_unique(), START + DAY);
+        user_stake.set_disputed(true);
+
+        let admin_account = token_account_info(
+            get_associated_token_address(&test_vault.admin, &mint),
+            mint,
+            test_vault.admin,
+        );
+        let infos = resolve_dispute_infos(&test_vault, user_stake_key, &user_stake, &admin_account);
+        assert!(run_instruction!(ResolveDispute, resolve_dispute, infos, false).is_err());
+        assert_eq!(token_balance(&test_vault.token_account), 5_000);
+    }
+
+    #[test]
+    fn finalized_payout_goes_to_the_recorded_destination() {
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut test_vault = TestVault::new(mint, 5_000);
+        test_vault.vault.add_outstanding_payout(1_100).unwrap();
+
+        let (user_stake_key, mut user_stake) = test_vault.stake_for(user, 1_000, 1);
+        user_stake.mark_as_withdrawn();
+        let destination =
+            token_account_info(get_associated_token_address(&user, &mint), mint, user);
+        user_stake.hold_payout(1_100, 100, *destination.key, START + DAY);
+
+        let infos = vec![
+            test_vault.info(),
+            program_account_info(user_stake_key, &user_stake),
+            destination.clone(),
+            test_vault.token_account.clone(),
+            mint_info(mint),
+            none_info(),
+            none_info(),
+            none_info(),
+            executable_info(spl_token::ID),
+        ];
+
+        set_clock(START + DAY - 1, 10);
+        assert_eq!(
+            run_instruction!(FinalizeWithdrawal, finalize_withdrawal, infos).unwrap_err(),
+            VaultError::DisputeWindowOpen.into()
+        );
 
-#[error_code]
-pub enum VaultError {
-    #[msg("Amount must be greater than 0")]
-    InvalidAmount,
+        set_clock(START + DAY, 11);
+        run_instruction!(FinalizeWithdrawal, finalize_withdrawal, infos).unwrap();
+        assert_eq!(token_balance(&destination), 1_100);
+        assert_eq!(token_balance(&test_vault.token_account), 3_900);
+        assert_eq!(load::<Vault>(&infos[0]).outstanding_payouts, 0);
+    }
 
-    #[msg("Stake period must be 1 or 2 years")]
-    InvalidStakePeriod,
+    fn compound_across_infos(
+        source: &TestVault,
+        source_stake: (Pubkey, &UserStake),
+        target: &TestVault,
+        target_stake: (Pubkey, &UserStake),
+        user: Pubkey,
+    ) -> Vec<AccountInfo<'static>> {
+        vec![
+            source.info(),
+            program_account_info(source_stake.0, source_stake.1),
+            source.token_account.clone(),
+            target.info(),
+            program_account_info(target_stake.0, target_stake.1),
+            target.token_account.clone(),
+            signer_info(user),
+            funded_token_account_info(
+                get_associated_token_address(&user, &source.mint),
+                source.mint,
+                user,
+                0,
+            ),
+            none_info(),
+            mint_info(source.mint),
+            none_info(),
+            executable_info(spl_token::ID),
+        ]
+    }
 
-    #[msg("Tokens are still locked")]
-    StillLocked,
+    type TestStake = (Pubkey, UserStake);
 
-    #[msg("Stake already withdrawn")]
-    AlreadyWithdrawn,
+    fn compound_across_vaults(user: Pubkey) -> (TestVault, TestStake, TestVault, TestStake) {
+        let mint = Pubkey::new_unique();
+        let mut source = TestVault::new(mint, 5_000);
+        let mut target = TestVault::new(mint, 5_000);
 
-    #[msg("Unauthorized user")]
-    UnauthorizedUser,
+        let (source_key, mut source_stake) = source.stake_for(user, 1_000, 2);
+        let source_reward = source_stake
+            .calculate_committed_reward(&source.vault)
+            .unwrap();
+        source.vault.add_staked(1_000).unwrap();
+        source.vault.commit_reward(source_reward).unwrap();
+        source_stake.set_committed_reward(source_reward);
 
-    #[msg("Math overflow error")]
-    MathOverflow,
+        let (target_key, mut target_stake) = target.stake_for(user, 1_000, 2);
+        target_stake
+            .create_stake(
+                user,
+                1_000,
+                2,
+                &clock_at(START + DAY, 1),
+                0,
+                target_stake.bump,
+            )
+            .unwrap();
+        let target_reward = target_stake
+            .calculate_committed_reward(&target.vault)
+            .unwrap();
+        target.vault.add_staked(1_000).unwrap();
+        target.vault.commit_reward(target_reward).unwrap();
+        target_stake.set_committed_reward(target_reward);
+
+        (
+            source,
+            (source_key, source_stake),
+            target,
+            (target_key, target_stake),
+        )
+    }
+
+    #[test]
+    fn compound_across_moves_reward_between_vaults() {
+        let user = Pubkey::new_unique();
+        let (source, (source_key, source_stake), target, (target_key, target_stake)) =
+            compound_across_vaults(user);
+        let reward = source_stake.committed_reward;
+
+        let infos = compound_across_infos(
+            &source,
+            (source_key, &source_stake),
+            &target,
+            (target_key, &target_stake),
+            user,
+        );
+        set_clock(source_stake.unlock_time, 10);
+        run_instruction!(CompoundAcross, compound_across, infos).unwrap();
+
+        assert_eq!(token_balance(&source.token_account), 5_000 - reward);
+        assert_eq!(token_balance(&target.token_account), 5_000 + reward);
+
+        let source_vault: Vault = load(&infos[0]);
+        let source_stake: UserStake = load(&infos[1]);
+        let target_vault: Vault = load(&infos[3]);
+        let target_stake: UserStake = load(&infos[4]);
+        assert_eq!(source_vault.committed_rewards, 0);
+        assert_eq!(source_vault.total_staked, 1_000);
+        assert_eq!(source_stake.committed_reward, 0);
+        assert_eq!(target_vault.total_staked, 1_000 + reward);
+        assert_eq!(target_stake.amount, 1_000 + reward);
+        assert_eq!(
+            target_vault.committed_rewards,
+            target_stake
+                .calculate_committed_reward(&target_vault)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn compound_across_respects_payout_restrictions() {
+        let user = Pubkey::new_unique();
+        let (mut source, (source_key, mut source_stake), target, (target_key, target_stake)) =
+            compound_across_vaults(user);
+        set_clock(source_stake.unlock_time, 10);
+
+        source.vault.dispute_window_seconds = DAY;
+        let infos = compound_across_infos(
+            &source,
+            (source_key, &source_stake),
+            &target,
+            (target_key, &target_stake),
+            user,
+        );
+        assert_eq!(
+            run_instruction!(CompoundAcross, compound_across, infos).unwrap_err(),
+            VaultError::RewardPayoutRestricted.into()
+        );
+
+        source.vault.dispute_window_seconds = 0;
+        source_stake.set_savings_lock(DAY);
+        let infos = compound_across_infos(
+            &source,
+            (source_key, &source_stake),
+            &target,
+            (target_key, &target_stake),
+            user,
+        );
+        assert_eq!(
+            run_instruction!(CompoundAcross, compound_across, infos).unwrap_err(),
+            VaultError::RewardPayoutRestricted.into()
+        );
+
+        source_stake.set_savings_lock(0);
+        set_clock(source_stake.unlock_time, 1);
+        let infos = compound_across_infos(
+            &source,
+            (source_key, &source_stake),
+            &target,
+            (target_key, &target_stake),
+            user,
+        );
+        assert_eq!(
+            run_instruction!(CompoundAcross, compound_across, infos).unwrap_err(),
+            VaultError::SameSlotWithdrawal.into()
+        );
+        assert_eq!(token_balance(&source.token_account), 5_000);
+        assert_eq!(token_balance(&target.token_account), 5_000);
+    }
+
+    struct TestStaker {
+        user: Pubkey,
+        stake: AccountInfo<'static>,
+        stats: AccountInfo<'static>,
+        profile: AccountInfo<'static>,
+        token_account: AccountInfo<'static>,
+    }
+
+    impl TestStaker {
+        fn new(test_vault: &TestVault, balance: u64) -> Self {
+            let user = Pubkey::new_unique();
+            let (stake_key, _) = Pubkey::find_program_address(
+                &[b"user_stake", test_vault.key.as_ref(), user.as_ref()],
+                &crate::ID,
+            );
+            let (profile_key, _) =
+                Pubkey::find_program_address(&[b"user_profile", user.as_ref()], &crate::ID);
+
+            TestStaker {
+                user,
+                stake: program_account_info(stake_key, &zeroed::<UserStake>()),
+                stats: test_vault.stats_info(user, 0),
+                profile: program_account_info(profile_key, &zeroed::<UserProfile>()),
+                token_account: funded_token_account_info(
+                    get_associated_token_address(&user, &test_vault.mint),
+                    test_vault.mint,
+                    user,
+                    balance,
+                ),
+            }
+        }
+
+        fn stake_infos(
+            &self,
+            vault: &AccountInfo<'static>,
+            test_vault: &TestVault,
+        ) -> Vec<AccountInfo<'static>> {
+            vec![
+                vault.clone(),
+                self.stake.clone(),
+                signer_info(self.user),
+                self.stats.clone(),
+                self.profile.clone(),
+                self.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                executable_info(spl_token::ID),
+                executable_info(anchor_spl::associated_token::ID),
+                executable_info(system_program::ID),
+            ]
+        }
+
+        fn withdraw_infos(
+            &self,
+            vault: &AccountInfo<'static>,
+            test_vault: &TestVault,
+        ) -> Vec<AccountInfo<'static>> {
+            vec![
+                vault.clone(),
+                self.stake.clone(),
+                signer_info(self.user),
+                self.stats.clone(),
+                self.profile.clone(),
+                self.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                none_info(),
+                none_info(),
+                none_info(),
+                none_info(),
+                none_info(),
+                executable_info(spl_token::ID),
+                executable_info(anchor_spl::associated_token::ID),
+                executable_info(system_program::ID),
+            ]
+        }
+
+        fn complete_stake(&self, vault: &AccountInfo<'static>, test_vault: &TestVault, start: i64) {
+            set_clock(start, 1);
+            let infos = self.stake_infos(vault, test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+
+            set_clock(start + SECONDS_PER_YEAR, 2);
+            let infos = self.withdraw_infos(vault, test_vault);
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        }
+    }
+
+    #[test]
+    fn streak_bonus_is_committed_and_paid_per_completed_stake() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault.vault.set_streak_bonus(100, 150);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 1_000);
+
+        for (round, paid_reward) in [0, 10, 15].into_iter().enumerate() {
+            let balance = token_balance(&staker.token_account);
+            let start = START + 2 * round as i64 * SECONDS_PER_YEAR;
+            set_clock(start, 1);
+            let infos = staker.stake_infos(&vault, &test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+            assert_eq!(load::<Vault>(&vault).committed_rewards, paid_reward);
+
+            set_clock(start + SECONDS_PER_YEAR, 2);
+            let infos = staker.withdraw_infos(&vault, &test_vault);
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+            assert_eq!(token_balance(&staker.token_account), balance + paid_reward);
+        }
+
+        assert_eq!(load::<UserStats>(&staker.stats).current_streak, 3);
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 25);
+    }
+
+    #[test]
+    fn early_exit_breaks_the_streak() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault.vault.set_streak_bonus(100, 1_000);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 1_000);
+        staker.complete_stake(&vault, &test_vault, START);
+        staker.complete_stake(&vault, &test_vault, START + 2 * SECONDS_PER_YEAR);
+        assert_eq!(load::<UserStats>(&staker.stats).current_streak, 2);
+
+        let start = START + 4 * SECONDS_PER_YEAR;
+        set_clock(start, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+        assert_eq!(load::<UserStake>(&staker.stake).streak_bonus_bps, 200);
+
+        let infos = vec![
+            vault.clone(),
+            staker.stake.clone(),
+            signer_info(staker.user),
+        ];
+        run_instruction!(UpdateStake, set_commitment_goal, infos, start + DAY).unwrap();
+
+        let balance = token_balance(&staker.token_account);
+        set_clock(start + SECONDS_PER_YEAR, 2);
+        let infos = staker.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+
+        assert_eq!(token_balance(&staker.token_account), balance + 1_000);
+        assert_eq!(load::<UserStats>(&staker.stats).current_streak, 0);
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 0);
+
+        set_clock(start + 2 * SECONDS_PER_YEAR, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+        assert_eq!(load::<UserStake>(&staker.stake).streak_bonus_bps, 0);
+    }
+
+    #[test]
+    fn committed_reward_covers_veteran_and_scheduled_epoch_bonus() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault.vault.set_veteran_bonus(100, 10_000);
+        test_vault
+            .vault
+            .set_loyalty_epoch(START + DAY, START + 2 * DAY, 1_000, 50);
+        let vault = test_vault.info();
+
+        let mut staker = TestStaker::new(&test_vault, 1_000);
+        let mut user_profile: UserProfile = zeroed();
+        user_profile.record_stake(staker.user, 0, START - 3 * SECONDS_PER_YEAR);
+        staker.profile = program_account_info(*staker.profile.key, &user_profile);
+
+        set_clock(START, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+
+        let user_stake: UserStake = load(&staker.stake);
+        assert_eq!(user_stake.veteran_bonus_bps, 400);
+        assert_eq!(user_stake.committed_reward, 45);
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 45);
+
+        set_clock(user_stake.unlock_time + 2 * SECONDS_PER_YEAR, 2);
+        let infos = staker.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+
+        assert_eq!(token_balance(&staker.token_account), 1_045);
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 45);
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 0);
+    }
+
+    fn claim_management_fee_infos(
+        vault: &AccountInfo<'static>,
+        test_vault: &TestVault,
+        admin_token_account: &AccountInfo<'static>,
+    ) -> Vec<AccountInfo<'static>> {
+        vec![
+            vault.clone(),
+            signer_info(test_vault.admin),
+            admin_token_account.clone(),
+            test_vault.token_account.clone(),
+            mint_info(test_vault.mint),
+            executable_info(spl_token::ID),
+            executable_info(anchor_spl::associated_token::ID),
+            executable_info(system_program::ID),
+        ]
+    }
+
+    #[test]
+    fn management_fee_claim_leaves_committed_rewards_and_buffer() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 12_000);
+        test_vault.vault.set_management_fee(1_000);
+        test_vault.vault.set_reserve_buffer(15_000);
+        test_vault.vault.add_staked(10_000).unwrap();
+        test_vault.vault.commit_reward(1_000).unwrap();
+        let vault = test_vault.info();
+        let admin_token_account = token_account_info(
+            get_associated_token_address(&test_vault.admin, &test_vault.mint),
+            test_vault.mint,
+            test_vault.admin,
+        );
+
+        set_clock(START + SECONDS_PER_YEAR, 1);
+        let infos = claim_management_fee_infos(&vault, &test_vault, &admin_token_account);
+        run_instruction!(ClaimManagementFee, claim_management_fee, infos).unwrap();
+
+        assert_eq!(token_balance(&admin_token_account), 500);
+        assert_eq!(token_balance(&test_vault.token_account), 11_500);
+        assert_eq!(load::<Vault>(&vault).accrued_management_fee, 500);
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        let infos = claim_management_fee_infos(&vault, &test_vault, &admin_token_account);
+        assert_eq!(
+            run_instruction!(ClaimManagementFee, claim_management_fee, infos).unwrap_err(),
+            VaultError::NoFeeSurplus.into()
+        );
+        assert_eq!(token_balance(&test_vault.token_account), 11_500);
+    }
+
+    #[test]
+    fn loyalty_reward_is_paid_in_loyalty_mint_units() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000_000);
+        let loyalty_mint = authority_mint_info(Pubkey::new_unique(), test_vault.key, 9);
+        test_vault.vault.set_loyalty_mint(*loyalty_mint.key);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 2_000_000);
+        let loyalty_token_account =
+            token_account_info(Pubkey::new_unique(), *loyalty_mint.key, staker.user);
+
+        set_clock(START, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_500_000, 2, true).unwrap();
+
+        set_clock(START + 2 * SECONDS_PER_YEAR, 2);
+        let mut infos = staker.withdraw_infos(&vault, &test_vault);
+        infos[8] = loyalty_mint.clone();
+        infos[9] = loyalty_token_account.clone();
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+
+        // 1.5 stake tokens (6 decimals) locked for 2 years earn 3 loyalty tokens (9 decimals).
+        assert_eq!(token_balance(&loyalty_token_account), 3_000_000_000);
+        assert_eq!(token_balance(&staker.token_account), 3_500_000);
+    }
+
+    #[test]
+    fn held_withdrawal_mints_loyalty_only_when_finalized() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        let loyalty_mint = authority_mint_info(Pubkey::new_unique(), test_vault.key, 6);
+        test_vault.vault.set_loyalty_mint(*loyalty_mint.key);
+        test_vault.vault.set_dispute_window(DAY);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 1_000);
+        let loyalty_token_account =
+            token_account_info(Pubkey::new_unique(), *loyalty_mint.key, staker.user);
+
+        set_clock(START, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+
+        let unlock_time = START + SECONDS_PER_YEAR;
+        set_clock(unlock_time, 2);
+        let mut infos = staker.withdraw_infos(&vault, &test_vault);
+        infos[8] = loyalty_mint.clone();
+        infos[9] = loyalty_token_account.clone();
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+
+        assert_eq!(token_balance(&loyalty_token_account), 0);
+        assert_eq!(load::<UserStake>(&staker.stake).pending_loyalty, 1_000);
+
+        let finalize_infos = |loyalty_token_account: &AccountInfo<'static>| {
+            vec![
+                vault.clone(),
+                staker.stake.clone(),
+                staker.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                none_info(),
+                loyalty_mint.clone(),
+                loyalty_token_account.clone(),
+                executable_info(spl_token::ID),
+            ]
+        };
+
+        set_clock(unlock_time + DAY, 3);
+        let elsewhere = token_account_info(
+            Pubkey::new_unique(),
+            *loyalty_mint.key,
+            Pubkey::new_unique(),
+        );
+        assert!(run_instruction!(
+            FinalizeWithdrawal,
+            finalize_withdrawal,
+            finalize_infos(&elsewhere)
+        )
+        .is_err());
+
+        run_instruction!(
+            FinalizeWithdrawal,
+            finalize_withdrawal,
+            finalize_infos(&loyalty_token_account)
+        )
+        .unwrap();
+
+        assert_eq!(token_balance(&loyalty_token_account), 1_000);
+        assert_eq!(token_balance(&staker.token_account), 1_000);
+        assert_eq!(load::<UserStake>(&staker.stake).pending_loyalty, 0);
+    }
+
+    #[test]
+    fn rejected_dispute_drops_the_held_loyalty() {
+        let user = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let mut test_vault = TestVault::new(mint, 5_000);
+        test_vault.vault.add_outstanding_payout(1_100).unwrap();
+
+        let (user_stake_key, mut user_stake) = test_vault.stake_for(user, 1_000, 1);
+        user_stake.mark_as_withdrawn();
+        let user_token_account =
+            token_account_info(get_associated_token_address(&user, &mint), mint, user);
+        user_stake.hold_payout(1_100, 100, *user_token_account.key, START + DAY);
+        user_stake.hold_loyalty(1_000);
+        user_stake.set_disputed(true);
+
+        let infos = resolve_dispute_infos(
+            &test_vault,
+            user_stake_key,
+            &user_stake,
+            &user_token_account,
+        );
+        run_instruction!(ResolveDispute, resolve_dispute, infos, false).unwrap();
+
+        assert_eq!(load::<UserStake>(&infos[1]).pending_loyalty, 0);
+    }
+
+    fn create_vault_infos(admin: Pubkey, mint: AccountInfo<'static>) -> Vec<AccountInfo<'static>> {
+        let (vault_key, _) = Pubkey::find_program_address(&[b"vault", admin.as_ref()], &crate::ID);
+        let vault = account_info(
+            vault_key,
+            system_program::ID,
+            vec![0; 8 + Vault::INIT_SPACE],
+        );
+        **vault.lamports.borrow_mut() = 0;
+        let admin = signer_info(admin);
+        **admin.lamports.borrow_mut() = 1_000_000_000;
+
+        vec![vault, admin, mint, executable_info(system_program::ID)]
+    }
+
+    #[test]
+    fn create_vault_rejects_non_classic_mints() {
+        let admin = Pubkey::new_unique();
+        let mint = mint_info(Pubkey::new_unique());
+        let infos = create_vault_infos(admin, mint);
+        set_clock(START, 1);
+        run_instruction!(CreateVault, create_vault, infos).unwrap();
+
+        let vault: Vault = load(&infos[0]);
+        assert_eq!(vault.admin, admin);
+        assert_eq!(*infos[0].owner, crate::ID);
+
+        let token_2022 = Pubkey::from_str_const("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        let rebasing_mint = account_info(Pubkey::new_unique(), token_2022, data);
+        let infos = create_vault_infos(Pubkey::new_unique(), rebasing_mint);
+        assert_eq!(
+            run_instruction!(CreateVault, create_vault, infos).unwrap_err(),
+            VaultError::RebasingMintUnsupported.into()
+        );
+    }
+
+    #[test]
+    fn rebased_vault_balance_is_not_reconciled() {
+        let test_vault = TestVault::new(Pubkey::new_unique(), 0);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 1_000);
+
+        set_clock(START, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+
+        // Simulate a negative rebase shrinking the vault's balance.
+        let mut account =
+            spl_token::state::Account::unpack(&test_vault.token_account.data.borrow()).unwrap();
+        account.amount = 900;
+        account.pack_into_slice(&mut test_vault.token_account.data.borrow_mut());
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        let infos = staker.withdraw_infos(&vault, &test_vault);
+        assert!(run_instruction!(WithdrawStake, withdraw_stake, infos).is_err());
+
+        let user_stake: UserStake = load(&staker.stake);
+        assert_eq!(user_stake.amount, 1_000);
+        assert!(!user_stake.is_withdrawn);
+        assert_eq!(token_balance(&test_vault.token_account), 900);
+    }
+
+    #[test]
+    fn distribute_rewards_funds_each_vault_ata_by_weight() {
+        let mint = Pubkey::new_unique();
+        let vaults = [
+            TestVault::new(mint, 0),
+            TestVault::new(mint, 0),
+            TestVault::new(mint, 0),
+        ];
+        let funder = Pubkey::new_unique();
+        let funder_token_account =
+            funded_token_account_info(Pubkey::new_unique(), mint, funder, 1_000);
+        let accounts = vec![
+            signer_info(funder),
+            funder_token_account.clone(),
+            mint_info(mint),
+            executable_info(spl_token::ID),
+        ];
+
+        let mut infos = accounts.clone();
+        for test_vault in &vaults {
+            infos.push(test_vault.info());
+            infos.push(test_vault.token_account.clone());
+        }
+        run_instruction!(
+            DistributeRewards,
+            distribute_rewards,
+            infos,
+            1_000,
+            vec![5_000, 3_000, 2_000]
+        )
+        .unwrap();
+
+        assert_eq!(token_balance(&vaults[0].token_account), 500);
+        assert_eq!(token_balance(&vaults[1].token_account), 300);
+        assert_eq!(token_balance(&vaults[2].token_account), 200);
+        assert_eq!(token_balance(&funder_token_account), 0);
+
+        let stray = token_account_info(Pubkey::new_unique(), mint, vaults[0].key);
+        let mut infos = accounts.clone();
+        infos.push(vaults[0].info());
+        infos.push(stray.clone());
+        assert_eq!(
+            run_instruction!(
+                DistributeRewards,
+                distribute_rewards,
+                infos,
+                1,
+                vec![10_000]
+            )
+            .unwrap_err(),
+            VaultError::InvalidDistributionTarget.into()
+        );
+    }
+
+    #[test]
+    fn lapsed_commitment_releases_its_committed_reward() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 1_000);
+        test_vault.vault.set_reserve_buffer(10_000);
+        test_vault.vault.set_commitment_forfeit(2_500);
+        let vault = test_vault.info();
+        let failing = TestStaker::new(&test_vault, 1_000);
+        let honest = TestStaker::new(&test_vault, 1_000);
+
+        set_clock(START, 1);
+        let infos = failing.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 2, true).unwrap();
+        let infos = vec![
+            vault.clone(),
+            failing.stake.clone(),
+            signer_info(failing.user),
+        ];
+        run_instruction!(UpdateStake, set_commitment_goal, infos, START + DAY).unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 1_000);
+
+        let reward_reserve =
+            load::<Vault>(&vault).reward_reserve(token_balance(&test_vault.token_account));
+        assert_eq!(
+            load::<Vault>(&vault)
+                .check_reserve_buffer(reward_reserve, 1_000)
+                .unwrap_err(),
+            VaultError::InsufficientReserveBuffer.into()
+        );
+
+        let admin_infos = vec![
+            vault.clone(),
+            failing.stake.clone(),
+            signer_info(test_vault.admin),
+        ];
+        assert_eq!(
+            run_instruction!(
+                AdminUpdateStake,
+                release_forfeited_reward,
+                admin_infos.clone()
+            )
+            .unwrap_err(),
+            VaultError::CommitmentPending.into()
+        );
+
+        set_clock(START + DAY + 1, 2);
+        run_instruction!(AdminUpdateStake, release_forfeited_reward, admin_infos).unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 0);
+        assert_eq!(load::<UserStake>(&failing.stake).committed_reward, 0);
+
+        let infos = honest.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 2, true).unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 1_000);
+
+        set_clock(START + 2 * SECONDS_PER_YEAR, 3);
+        let infos = failing.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&failing.token_account), 750);
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 1_000);
+
+        set_clock(START + DAY + 1 + 2 * SECONDS_PER_YEAR, 4);
+        let infos = honest.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&honest.token_account), 2_000);
+        assert_eq!(token_balance(&test_vault.token_account), 250);
+    }
+
+    struct TestReserveStaker {
+        user: Pubkey,
+        reserve_stake: AccountInfo<'static>,
+        token_account: AccountInfo<'static>,
+    }
+
+    impl TestReserveStaker {
+        fn new(test_vault: &TestVault, balance: u64) -> Self {
+            let user = Pubkey::new_unique();
+            let (reserve_stake_key, _) = Pubkey::find_program_address(
+                &[b"reserve_stake", test_vault.key.as_ref(), user.as_ref()],
+                &crate::ID,
+            );
+
+            TestReserveStaker {
+                user,
+                reserve_stake: program_account_info(reserve_stake_key, &zeroed::<ReserveStake>()),
+                token_account: funded_token_account_info(
+                    get_associated_token_address(&user, &test_vault.mint),
+                    test_vault.mint,
+                    user,
+                    balance,
+                ),
+            }
+        }
+
+        fn stake_infos(
+            &self,
+            vault: &AccountInfo<'static>,
+            test_vault: &TestVault,
+        ) -> Vec<AccountInfo<'static>> {
+            vec![
+                vault.clone(),
+                self.reserve_stake.clone(),
+                signer_info(self.user),
+                self.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                executable_info(spl_token::ID),
+                executable_info(anchor_spl::associated_token::ID),
+                executable_info(system_program::ID),
+            ]
+        }
+
+        fn unstake_infos(
+            &self,
+            vault: &AccountInfo<'static>,
+            test_vault: &TestVault,
+        ) -> Vec<AccountInfo<'static>> {
+            vec![
+                vault.clone(),
+                self.reserve_stake.clone(),
+                signer_info(self.user),
+                self.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                executable_info(spl_token::ID),
+            ]
+        }
+    }
+
+    #[test]
+    fn reserve_shortfall_slashes_reserve_stakers_before_principal() {
+        let test_vault = TestVault::new(Pubkey::new_unique(), 0);
+        let vault = test_vault.info();
+        let insurer = TestReserveStaker::new(&test_vault, 1_000);
+        let earner = TestStaker::new(&test_vault, 500);
+        let holder = TestStaker::new(&test_vault, 1_000);
+
+        set_clock(START, 1);
+        let infos = insurer.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeReserve, stake_reserve, infos, 1_000).unwrap();
+        assert_eq!(load::<ReserveStake>(&insurer.reserve_stake).shares, 1_000);
+
+        let infos = earner.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 500, 2, true).unwrap();
+        let infos = holder.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 500);
+
+        set_clock(START + 2 * SECONDS_PER_YEAR, 2);
+        let infos = earner.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&earner.token_account), 1_000);
+
+        let infos = insurer.unstake_infos(&vault, &test_vault);
+        run_instruction!(UnstakeReserve, unstake_reserve, infos).unwrap();
+        assert_eq!(token_balance(&insurer.token_account), 500);
+        assert_eq!(load::<ReserveStake>(&insurer.reserve_stake).shares, 0);
+        assert_eq!(load::<Vault>(&vault).reserve_staked, 0);
+
+        let infos = holder.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&holder.token_account), 1_000);
+        assert_eq!(token_balance(&test_vault.token_account), 0);
+    }
+
+    #[test]
+    fn management_fee_is_shared_with_reserve_stakers() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 12_000);
+        test_vault.vault.set_management_fee(1_000);
+        test_vault.vault.set_reserve_fee_share(5_000);
+        test_vault.vault.add_staked(10_000).unwrap();
+        let vault = test_vault.info();
+        let insurer = TestReserveStaker::new(&test_vault, 1_000);
+        let admin_token_account = token_account_info(
+            get_associated_token_address(&test_vault.admin, &test_vault.mint),
+            test_vault.mint,
+            test_vault.admin,
+        );
+
+        set_clock(START, 1);
+        let infos = insurer.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeReserve, stake_reserve, infos, 1_000).unwrap();
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        let infos = claim_management_fee_infos(&vault, &test_vault, &admin_token_account);
+        run_instruction!(ClaimManagementFee, claim_management_fee, infos).unwrap();
+        assert_eq!(token_balance(&admin_token_account), 500);
+        assert_eq!(load::<Vault>(&vault).accrued_management_fee, 0);
+        assert_eq!(load::<Vault>(&vault).reserve_staked, 1_500);
+
+        let infos = insurer.unstake_infos(&vault, &test_vault);
+        run_instruction!(UnstakeReserve, unstake_reserve, infos).unwrap();
+        assert_eq!(token_balance(&insurer.token_account), 1_500);
+        assert_eq!(token_balance(&test_vault.token_account), 11_000);
+
+        let infos = insurer.unstake_infos(&vault, &test_vault);
+        assert_eq!(
+            run_instruction!(UnstakeReserve, unstake_reserve, infos).unwrap_err(),
+            VaultError::NoReserveStake.into()
+        );
+    }
+
+    #[test]
+    fn wiped_out_reserve_shares_do_not_claim_new_deposits() {
+        let mut vault = new_vault();
+        let mut wiped: ReserveStake = zeroed();
+        wiped
+            .deposit(vault.add_reserve_stake(1_000).unwrap(), vault.reserve_epoch)
+            .unwrap();
+
+        vault.sync_reserve_stake(0);
+        assert_eq!(vault.reserve_stake_value(wiped.shares).unwrap(), 0);
+
+        assert_eq!(vault.add_reserve_stake(400).unwrap(), 400);
+        assert_eq!(vault.reserve_epoch, 1);
+        assert_eq!(wiped.active_shares(vault.reserve_epoch), 0);
+        assert_eq!(vault.reserve_stake_value(400).unwrap(), 400);
+    }
+
+    #[test]
+    fn rate_history_records_admin_rate_changes_in_order() {
+        let test_vault = TestVault::new(Pubkey::new_unique(), 0);
+        let vault = test_vault.info();
+        let admin_infos = || vec![vault.clone(), signer_info(test_vault.admin)];
+
+        set_clock(START, 1);
+        run_instruction!(UpdateVault, set_streak_bonus, admin_infos(), 100, 500).unwrap();
+        set_clock(START + DAY, 2);
+        run_instruction!(UpdateVault, set_streak_bonus, admin_infos(), 150, 500).unwrap();
+        set_clock(START + 2 * DAY, 3);
+        run_instruction!(UpdateVault, set_keeper_tip, admin_infos(), 25).unwrap();
+
+        let history = load::<Vault>(&vault).rate_history;
+        let entries: Vec<_> = history
+            .iter()
+            .map(|entry| (entry.rate, entry.timestamp, entry.old_bps, entry.new_bps))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (RATE_STREAK_BONUS, START, 0, 100),
+                (RATE_MAX_STREAK_BONUS, START, 0, 500),
+                (RATE_STREAK_BONUS, START + DAY, 100, 150),
+                (RATE_KEEPER_TIP, START + 2 * DAY, 0, 25),
+            ]
+        );
+
+        for change in 1..=RATE_HISTORY_CAPACITY as u16 {
+            set_clock(START + (2 + change as i64) * DAY, 3 + change as u64);
+            run_instruction!(UpdateVault, set_max_lock_bonus, admin_infos(), change).unwrap();
+        }
+
+        let vault = load::<Vault>(&vault);
+        assert_eq!(vault.rate_history.len(), RATE_HISTORY_CAPACITY);
+        assert_eq!(vault.rate_history_total, 4 + RATE_HISTORY_CAPACITY as u64);
+        assert_eq!(vault.rate_history_next, 4);
+
+        let capacity = RATE_HISTORY_CAPACITY as u16;
+        let oldest = &vault.rate_history[vault.rate_history_next as usize];
+        assert_eq!(
+            (oldest.rate, oldest.old_bps, oldest.new_bps),
+            (RATE_MAX_LOCK_BONUS, 0, 1)
+        );
+        let newest = &vault.rate_history[vault.rate_history_next as usize - 1];
+        assert_eq!(
+            (newest.rate, newest.old_bps, newest.new_bps),
+            (RATE_MAX_LOCK_BONUS, capacity - 1, capacity)
+        );
+    }
+
+    #[test]
+    fn reward_ramp_cuts_stakes_unlocked_early_by_the_admin() {
+        let test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        let vault = test_vault.info();
+        let admin_infos = || vec![vault.clone(), signer_info(test_vault.admin)];
+
+        set_clock(START, 1);
+        assert_eq!(
+            run_instruction!(
+                UpdateVault,
+                set_reward_ramp,
+                admin_infos(),
+                SECONDS_PER_YEAR + 1
+            )
+            .unwrap_err(),
+            VaultError::InvalidRewardRamp.into()
+        );
+        run_instruction!(
+            UpdateVault,
+            set_reward_ramp,
+            admin_infos(),
+            SECONDS_PER_YEAR
+        )
+        .unwrap();
+
+        for (ramp_fraction, paid_reward) in [(4, 250), (2, 500), (1, 1_000)] {
+            let staker = TestStaker::new(&test_vault, 1_000);
+            set_clock(START, 1);
+            let infos = staker.stake_infos(&vault, &test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 2, true).unwrap();
+
+            let unlock_time = START + SECONDS_PER_YEAR / ramp_fraction;
+            let infos = vec![
+                vault.clone(),
+                staker.stake.clone(),
+                signer_info(test_vault.admin),
+                signer_info(staker.user),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+            ];
+            run_instruction!(
+                AdjustStake,
+                adjust_stake,
+                infos,
+                None,
+                Some(unlock_time),
+                None
+            )
+            .unwrap();
+
+            set_clock(unlock_time, 2);
+            let infos = staker.withdraw_infos(&vault, &test_vault);
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+            assert_eq!(token_balance(&staker.token_account), 1_000 + paid_reward);
+        }
+
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 1_750);
+    }
+
+    #[test]
+    fn management_fee_claim_refills_the_reward_reserve() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 12_000);
+        test_vault.vault.set_management_fee(1_000);
+        test_vault.vault.add_staked(10_000).unwrap();
+        let vault = test_vault.info();
+        let admin_token_account = token_account_info(
+            get_associated_token_address(&test_vault.admin, &test_vault.mint),
+            test_vault.mint,
+            test_vault.admin,
+        );
+
+        set_clock(START, 1);
+        let infos = vec![vault.clone(), signer_info(test_vault.admin)];
+        run_instruction!(UpdateVault, set_fee_to_reserve, infos, 2_500).unwrap();
+        assert_eq!(load::<Vault>(&vault).reward_reserve(12_000), 2_000);
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        let infos = claim_management_fee_infos(&vault, &test_vault, &admin_token_account);
+        run_instruction!(ClaimManagementFee, claim_management_fee, infos).unwrap();
+
+        assert_eq!(token_balance(&admin_token_account), 750);
+        assert_eq!(token_balance(&test_vault.token_account), 11_250);
+        let vault = load::<Vault>(&vault);
+        assert_eq!(vault.accrued_management_fee, 0);
+        assert_eq!(vault.reward_reserve(11_250), 1_250);
+    }
+
+    #[test]
+    fn forfeit_appeal_restores_or_upholds_the_forfeit() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault.vault.set_commitment_forfeit(2_500);
+        let vault = test_vault.info();
+        let upheld = TestStaker::new(&test_vault, 1_000);
+        let rejected = TestStaker::new(&test_vault, 1_000);
+
+        set_clock(START, 1);
+        for staker in [&upheld, &rejected] {
+            let infos = staker.stake_infos(&vault, &test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 2, true).unwrap();
+            let infos = vec![
+                vault.clone(),
+                staker.stake.clone(),
+                signer_info(staker.user),
+            ];
+            run_instruction!(UpdateStake, set_commitment_goal, infos, START + DAY).unwrap();
+        }
+
+        set_clock(START + DAY + 1, 2);
+        let admin_infos = |staker: &TestStaker| {
+            vec![
+                vault.clone(),
+                staker.stake.clone(),
+                signer_info(test_vault.admin),
+            ]
+        };
+        run_instruction!(
+            AdminUpdateStake,
+            release_forfeited_reward,
+            admin_infos(&upheld)
+        )
+        .unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 1_000);
+
+        for staker in [&upheld, &rejected] {
+            let infos = vec![
+                vault.clone(),
+                staker.stake.clone(),
+                signer_info(staker.user),
+            ];
+            run_instruction!(UpdateStake, appeal_forfeit, infos).unwrap();
+        }
+
+        set_clock(START + 2 * SECONDS_PER_YEAR, 3);
+        let infos = upheld.withdraw_infos(&vault, &test_vault);
+        assert_eq!(
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap_err(),
+            VaultError::AppealPending.into()
+        );
+
+        run_instruction!(AdminUpdateStake, resolve_appeal, admin_infos(&upheld), true).unwrap();
+        run_instruction!(
+            AdminUpdateStake,
+            resolve_appeal,
+            admin_infos(&rejected),
+            false
+        )
+        .unwrap();
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 2_000);
+
+        let infos = vec![
+            vault.clone(),
+            rejected.stake.clone(),
+            signer_info(rejected.user),
+        ];
+        assert_eq!(
+            run_instruction!(UpdateStake, appeal_forfeit, infos).unwrap_err(),
+            VaultError::AppealAlreadyFiled.into()
+        );
+
+        let infos = upheld.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&upheld.token_account), 2_000);
+
+        set_clock(START + 2 * SECONDS_PER_YEAR, 4);
+        let infos = rejected.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&rejected.token_account), 750);
+        assert_eq!(
+            token_balance(&test_vault.token_account),
+            10_000 - 1_000 + 250
+        );
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 0);
+    }
+
+    #[test]
+    fn longer_unbonding_period_earns_more_and_holds_the_payout() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault.vault.set_unbond_bonus(30 * DAY, 500);
+        let vault = test_vault.info();
+        let stakers = [
+            (TestStaker::new(&test_vault, 1_000), 0, 0),
+            (TestStaker::new(&test_vault, 1_000), 15 * DAY, 25),
+            (TestStaker::new(&test_vault, 1_000), 30 * DAY, 50),
+        ];
+        let unbond_infos = |staker: &TestStaker| {
+            vec![
+                vault.clone(),
+                staker.stake.clone(),
+                signer_info(staker.user),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+            ]
+        };
+
+        set_clock(START, 1);
+        for (staker, unbond_period, _) in &stakers {
+            let infos = staker.stake_infos(&vault, &test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+            run_instruction!(
+                SetUnbondPeriod,
+                set_unbond_period,
+                unbond_infos(staker),
+                *unbond_period
+            )
+            .unwrap();
+        }
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 75);
+        assert_eq!(
+            run_instruction!(
+                SetUnbondPeriod,
+                set_unbond_period,
+                unbond_infos(&stakers[2].0),
+                DAY
+            )
+            .unwrap_err(),
+            VaultError::InvalidUnbondPeriod.into()
+        );
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        for (staker, _, _) in &stakers {
+            let infos = staker.withdraw_infos(&vault, &test_vault);
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        }
+        assert_eq!(token_balance(&stakers[0].0.token_account), 1_000);
+        assert_eq!(token_balance(&stakers[1].0.token_account), 0);
+        assert_eq!(token_balance(&stakers[2].0.token_account), 0);
+
+        for (staker, unbond_period, reward) in &stakers[1..] {
+            let infos = vec![
+                vault.clone(),
+                staker.stake.clone(),
+                staker.token_account.clone(),
+                test_vault.token_account.clone(),
+                mint_info(test_vault.mint),
+                none_info(),
+                none_info(),
+                none_info(),
+                executable_info(spl_token::ID),
+            ];
+            set_clock(START + SECONDS_PER_YEAR + unbond_period - 1, 3);
+            assert_eq!(
+                run_instruction!(FinalizeWithdrawal, finalize_withdrawal, infos).unwrap_err(),
+                VaultError::DisputeWindowOpen.into()
+            );
+
+            set_clock(START + SECONDS_PER_YEAR + unbond_period, 4);
+            run_instruction!(FinalizeWithdrawal, finalize_withdrawal, infos).unwrap();
+            assert_eq!(token_balance(&staker.token_account), 1_000 + reward);
+        }
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 75);
+        assert_eq!(load::<Vault>(&vault).outstanding_payouts, 0);
+    }
+
+    #[test]
+    fn utilization_curve_locks_in_a_lower_rate_as_the_vault_fills() {
+        let mut test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        test_vault
+            .vault
+            .set_utilization_curve(4_000, 8_000, 1_000, 200);
+        let vault = test_vault.info();
+        let stakers = [
+            (TestStaker::new(&test_vault, 1_000), 1_000, 1_000, 100),
+            (TestStaker::new(&test_vault, 2_000), 2_000, 750, 150),
+            (TestStaker::new(&test_vault, 1_000), 1_000, 250, 25),
+        ];
+
+        set_clock(START, 1);
+        for (staker, amount, bonus_bps, _) in &stakers {
+            let infos = staker.stake_infos(&vault, &test_vault);
+            run_instruction!(StakeTokens, stake_tokens, infos, *amount, 1, true).unwrap();
+            assert_eq!(
+                load::<UserStake>(&staker.stake).utilization_bonus_bps,
+                *bonus_bps
+            );
+        }
+        assert_eq!(load::<Vault>(&vault).utilization_bps(), 10_000);
+        assert_eq!(load::<Vault>(&vault).calculate_utilization_bonus_bps(), 0);
+        assert_eq!(load::<Vault>(&vault).committed_rewards, 275);
+
+        assert_eq!(
+            run_instruction!(
+                UpdateVault,
+                set_utilization_curve,
+                vec![vault.clone(), signer_info(test_vault.admin)],
+                4_000,
+                10_000,
+                1_000,
+                200
+            )
+            .unwrap_err(),
+            VaultError::InvalidUtilizationCurve.into()
+        );
+
+        set_clock(START + SECONDS_PER_YEAR, 2);
+        for (staker, amount, _, reward) in &stakers {
+            let infos = staker.withdraw_infos(&vault, &test_vault);
+            run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+            assert_eq!(token_balance(&staker.token_account), amount + reward);
+        }
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 275);
+    }
+
+    #[test]
+    fn compound_split_divides_the_reward_between_principal_and_payout() {
+        for (compound_split_bps, compounded) in [(0, 0), (5_000, 500), (10_000, 1_000)] {
+            let user = Pubkey::new_unique();
+            let (source, (source_key, source_stake), target, (target_key, target_stake)) =
+                compound_across_vaults(user);
+            let reward = source_stake.committed_reward;
+            assert_eq!(reward, 1_000);
+
+            let infos = compound_across_infos(
+                &source,
+                (source_key, &source_stake),
+                &target,
+                (target_key, &target_stake),
+                user,
+            );
+            let split_infos = vec![infos[0].clone(), infos[1].clone(), infos[6].clone()];
+            assert_eq!(
+                run_instruction!(UpdateStake, set_compound_split, split_infos, 10_001).unwrap_err(),
+                VaultError::InvalidBasisPoints.into()
+            );
+            run_instruction!(
+                UpdateStake,
+                set_compound_split,
+                split_infos,
+                compound_split_bps
+            )
+            .unwrap();
+
+            set_clock(source_stake.unlock_time, 10);
+            run_instruction!(CompoundAcross, compound_across, infos).unwrap();
+
+            assert_eq!(token_balance(&source.token_account), 5_000 - reward);
+            assert_eq!(token_balance(&target.token_account), 5_000 + compounded);
+            assert_eq!(token_balance(&infos[7]), reward - compounded);
+
+            let source_vault: Vault = load(&infos[0]);
+            let target_vault: Vault = load(&infos[3]);
+            let target_stake: UserStake = load(&infos[4]);
+            assert_eq!(source_vault.committed_rewards, 0);
+            assert_eq!(target_vault.total_staked, 1_000 + compounded);
+            assert_eq!(target_stake.amount, 1_000 + compounded);
+        }
+    }
+
+    fn approve_delegate(token_account: &AccountInfo, delegate: Pubkey, amount: u64) {
+        let mut data = token_account.data.borrow_mut();
+        let mut account = spl_token::state::Account::unpack(&data).unwrap();
+        account.delegate = Some(delegate).into();
+        account.delegated_amount = amount;
+        account.pack_into_slice(&mut data);
+    }
+
+    #[test]
+    fn keeper_deposits_grow_the_stake_and_reward_consistent_timing() {
+        let test_vault = TestVault::new(Pubkey::new_unique(), 10_000);
+        let vault = test_vault.info();
+        let staker = TestStaker::new(&test_vault, 2_000);
+        let admin_infos = vec![vault.clone(), signer_info(test_vault.admin)];
+        run_instruction!(UpdateVault, set_dca_bonus, admin_infos, 100, 250).unwrap();
+
+        set_clock(START, 1);
+        let infos = staker.stake_infos(&vault, &test_vault);
+        run_instruction!(StakeTokens, stake_tokens, infos, 1_000, 1, true).unwrap();
+        approve_delegate(&staker.token_account, test_vault.key, 1_000);
+
+        let update_infos = vec![
+            vault.clone(),
+            staker.stake.clone(),
+            signer_info(staker.user),
+        ];
+        assert_eq!(
+            run_instruction!(UpdateStake, set_dca_schedule, update_infos, 250, 0).unwrap_err(),
+            VaultError::InvalidDcaSchedule.into()
+        );
+        run_instruction!(UpdateStake, set_dca_schedule, update_infos, 250, 30 * DAY).unwrap();
+
+        let deposit_infos = vec![
+            vault.clone(),
+            staker.stake.clone(),
+            account_info(staker.user, system_program::ID, Vec::new()),
+            signer_info(Pubkey::new_unique()),
+            staker.token_account.clone(),
+            test_vault.token_account.clone(),
+            mint_info(test_vault.mint),
+            executable_info(spl_token::ID),
+        ];
+        set_clock(START + 29 * DAY, 2);
+        assert_eq!(
+            run_instruction!(KeeperDeposit, keeper_deposit, deposit_infos).unwrap_err(),
+            VaultError::DcaDepositNotDue.into()
+        );
+
+        // Three on-time deposits climb to the cap; a missed interval restarts the run.
+        let schedule = [
+            (30, 1, 1_250, 12),
+            (60, 2, 1_500, 30),
+            (90, 3, 1_750, 43),
+            (200, 1, 2_000, 20),
+        ];
+        for (slot, (day, dca_deposits, amount, committed)) in schedule.into_iter().enumerate() {
+            set_clock(START + day * DAY, 3 + slot as u64);
+            run_instruction!(KeeperDeposit, keeper_deposit, deposit_infos).unwrap();
+
+            let user_stake = load::<UserStake>(&staker.stake);
+            assert_eq!(user_stake.dca_deposits, dca_deposits);
+            assert_eq!(user_stake.amount, amount);
+            assert_eq!(load::<Vault>(&vault).committed_rewards, committed);
+        }
+        assert_eq!(token_balance(&staker.token_account), 0);
+        assert_eq!(load::<Vault>(&vault).total_staked, 2_000);
+
+        set_clock(START + SECONDS_PER_YEAR, 10);
+        let infos = staker.withdraw_infos(&vault, &test_vault);
+        run_instruction!(WithdrawStake, withdraw_stake, infos).unwrap();
+        assert_eq!(token_balance(&staker.token_account), 2_020);
+        assert_eq!(token_balance(&test_vault.token_account), 10_000 - 20);
+    }
 }